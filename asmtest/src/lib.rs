@@ -0,0 +1,101 @@
+/*
+Runs the `.test` blocks of assembly files on the emulator. Every test gets a
+fresh machine with the program loaded, so tests do not affect each other.
+
+A failing test is reported with one line per difference:
+
+V1: expected 3, found 2
+[0x10]: expected 1, found 0
+output:
+    3
+  - 2
+  + 5
+*/
+
+use std::fmt::Display;
+
+use assembler::Test;
+use emulator::{coverage::Coverage, Machine, Step};
+use exe::Executable;
+
+/// Runs `test` on `program` and returns the differences from what it expects
+/// if it fails. The instructions that ran are recorded in `coverage` if given.
+pub fn run(program: &Executable, test: &Test, coverage: Option<&mut Coverage>) -> Result<(), Vec<String>> {
+    let mut machine = Machine::with_observer(program, coverage).map_err(|e| vec![e])?;
+    let mut failures = Vec::new();
+
+    if let Some(start) = test.start {
+        machine.set_pc(start);
+    }
+    for (register, value) in &test.registers {
+        machine.set_register(*register, *value);
+    }
+    for (address, value) in &test.memory {
+        machine.set_memory(*address, *value);
+    }
+    if let Some(register) = test.return_register {
+        // a program of 256 instructions ends past the 8-bit range, returning to its last address halts too
+        let end = u8::try_from(machine.program_size()).unwrap_or(u8::MAX);
+        machine.set_register(register, end);
+    }
+
+    let mut output = Vec::new();
+    let mut cycles = 0;
+    loop {
+        if cycles == test.cycles {
+            failures.push(format!("did not halt within {} cycles (PC {})", test.cycles, machine.pc()));
+            break;
+        }
+        match machine.step() {
+            Ok(Step::Running) => {},
+            Ok(Step::Output(value)) => output.push(value),
+            Ok(Step::Halted) => break,
+            Err(e) => {
+                failures.push(e);
+                break;
+            },
+        }
+        cycles += 1;
+    }
+
+    for (register, expected) in &test.expect_registers {
+        let found = machine.register(*register);
+        if found != *expected {
+            failures.push(format!("V{:X}: expected {}, found {}", register, expected, found));
+        }
+    }
+    for (address, expected) in &test.expect_memory {
+        let found = machine.memory(*address);
+        if found != *expected {
+            failures.push(format!("[0x{:02X}]: expected {}, found {}", address, expected, found));
+        }
+    }
+    if let Some(expected) = &test.expect_output {
+        if *expected != output {
+            failures.push(format!("output:\n{}", diff(expected, &output)));
+        }
+    }
+
+    if failures.is_empty() {
+        Ok(())
+    }
+    else {
+        Err(failures)
+    }
+}
+
+/// Compares the printed values one by one, `-` for the expected and `+` for
+/// the found value where they differ.
+pub fn diff<T: Display + PartialEq>(expected: &[T], found: &[T]) -> String {
+    let mut lines = Vec::new();
+    for i in 0..expected.len().max(found.len()) {
+        match (expected.get(i), found.get(i)) {
+            (Some(e), Some(f)) if e == f => lines.push(format!("    {}", e)),
+            (e, f) => {
+                lines.extend(e.map(|e| format!("  - {}", e)));
+                lines.extend(f.map(|f| format!("  + {}", f)));
+            },
+        }
+    }
+    lines.join("\n")
+}
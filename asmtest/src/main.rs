@@ -0,0 +1,201 @@
+use std::{env::args, fs, path::{Path, PathBuf}, process::ExitCode};
+
+use assembler::Options as AssemblerOptions;
+use emulator::coverage::Coverage;
+
+const USAGE: &str = "\
+Usage: asmtest [options] [<file.asm or directory>...]
+
+Runs the .test blocks of the given files and of the .asm files in the given
+directories (default: the current directory).
+
+Options:
+    -I <dir>           Search <dir> for .include files (can be repeated)
+    --filter <text>    Only run tests whose name contains <text>
+    --coverage <file>  Write an lcov coverage report of the tests to <file>
+    -h, --help         Print this help";
+
+struct Options {
+    assembler: AssemblerOptions,
+    filter: Option<String>,
+    coverage: Option<String>,
+    inputs: Vec<String>,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Option<Options>, String> {
+    let mut options = Options {
+        assembler: AssemblerOptions::default(),
+        filter: None,
+        coverage: None,
+        inputs: Vec::new(),
+    };
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-I" => {
+                options.assembler.include_paths.push(args.next().ok_or("-I expects a directory")?.into());
+            },
+            "--filter" => {
+                options.filter = Some(args.next().ok_or("--filter expects a text")?);
+            },
+            "--coverage" => {
+                options.coverage = Some(args.next().ok_or("--coverage expects a file")?);
+            },
+            "-h" | "--help" => {
+                return Ok(None);
+            },
+            _ if arg.starts_with('-') => {
+                return Err(format!("Unknown option: {}", arg));
+            },
+            _ => {
+                options.inputs.push(arg);
+            }
+        }
+    }
+
+    if options.inputs.is_empty() {
+        options.inputs.push(".".to_string());
+    }
+
+    Ok(Some(options))
+}
+
+fn main() -> ExitCode {
+    let options = match parse_args(args().skip(1)) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        },
+        Err(e) => {
+            eprintln!("error: {}", e);
+            eprintln!("{}", USAGE);
+            return ExitCode::from(2);
+        }
+    };
+
+    let mut files = Vec::new();
+    for input in &options.inputs {
+        if let Err(e) = find_files(Path::new(input), &mut files) {
+            eprintln!("error: {}: {}", input, e);
+            return ExitCode::from(2);
+        }
+    }
+
+    if run_all(&options, &files) {
+        ExitCode::SUCCESS
+    }
+    else {
+        ExitCode::FAILURE
+    }
+}
+
+/// Collects the `.asm` files at `path`, searching directories recursively but
+/// skipping hidden ones and `target`.
+fn find_files(path: &Path, files: &mut Vec<PathBuf>) -> Result<(), String> {
+    if !path.is_dir() {
+        files.push(path.to_path_buf());
+        return Ok(());
+    }
+
+    let mut entries = fs::read_dir(path)
+        .and_then(|entries| entries.map(|entry| entry.map(|entry| entry.path())).collect::<Result<Vec<_>, _>>())
+        .map_err(|e| e.to_string())?;
+    entries.sort();
+
+    for entry in entries {
+        let name = entry.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+        if entry.is_dir() {
+            if !name.starts_with('.') && name != "target" {
+                find_files(&entry, files)?;
+            }
+        }
+        else if entry.extension().is_some_and(|extension| extension == "asm") {
+            files.push(entry);
+        }
+    }
+    Ok(())
+}
+
+/// Runs the tests of all files and prints the results. Returns `true` if
+/// every test passed.
+fn run_all(options: &Options, files: &[PathBuf]) -> bool {
+    let mut programs = Vec::new();
+    let mut errors = 0;
+    for file in files {
+        let path = file.display().to_string();
+        match assembler::assemble_file(&path, &options.assembler) {
+            Ok(assembled) if assembled.tests.is_empty() => {},
+            Ok(assembled) => programs.push((path, assembled)),
+            // files without tests, like included fragments, do not have to assemble on their own
+            Err(_) if fs::read_to_string(file).is_ok_and(|source| !assembler::has_tests(&source)) => {},
+            Err(diagnostics) => {
+                diagnostics.iter().for_each(|diagnostic| eprintln!("error: {}: {}", path, diagnostic));
+                errors += 1;
+            },
+        }
+    }
+
+    let selected = |name: &str| options.filter.as_ref().is_none_or(|filter| name.contains(filter.as_str()));
+    let count = programs
+        .iter()
+        .flat_map(|(_, assembled)| &assembled.tests)
+        .filter(|test| selected(&test.name))
+        .count();
+    println!("\nrunning {} test{}", count, if count == 1 { "" } else { "s" });
+
+    let (mut passed, mut failed, mut filtered) = (0, Vec::new(), 0);
+    let mut lcov = String::new();
+    for (path, assembled) in &programs {
+        let mut coverage = Coverage::new();
+        for test in &assembled.tests {
+            if !selected(&test.name) {
+                filtered += 1;
+                continue;
+            }
+            let name = format!("{}: {}", path, test.name);
+            match asmtest::run(&assembled.program, test, Some(&mut coverage)) {
+                Ok(()) => {
+                    println!("test {} ... ok", name);
+                    passed += 1;
+                },
+                Err(failures) => {
+                    println!("test {} ... FAILED", name);
+                    failed.push((format!("{} ({})", name, test.location), failures));
+                },
+            }
+        }
+        if options.coverage.is_some() {
+            lcov.push_str(&coverage.lcov(&assembled.program, &assembled.debug));
+        }
+    }
+
+    if !failed.is_empty() {
+        println!("\nfailures:");
+        for (name, failures) in &failed {
+            println!("\n---- {} ----", name);
+            failures.iter().for_each(|failure| println!("{}", failure));
+        }
+        println!("\nfailures:");
+        failed.iter().for_each(|(name, _)| println!("    {}", name));
+    }
+
+    let ok = failed.is_empty() && errors == 0;
+    let mut summary = format!(
+        "\ntest result: {}. {} passed; {} failed; {} filtered out",
+        if ok { "ok" } else { "FAILED" }, passed, failed.len(), filtered
+    );
+    if errors > 0 {
+        summary.push_str(&format!("; {} file{} did not assemble", errors, if errors == 1 { "" } else { "s" }));
+    }
+    println!("{}\n", summary);
+
+    if let Some(path) = &options.coverage {
+        if let Err(e) = fs::write(path, lcov) {
+            eprintln!("error: {}: {}", path, e);
+            return false;
+        }
+    }
+
+    ok
+}
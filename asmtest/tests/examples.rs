@@ -0,0 +1,162 @@
+/*
+Golden-output tests for the programs in `examples/`. Every `.asm` file is
+assembled and run on the emulator, and what it prints is compared with the
+`.expected` file next to it: one printed value per line, or `error: ...`
+lines if the program does not assemble or fails while running. The
+`.test` blocks of the program are run with `asmtest::run` after it, one
+`test name ... ok` or `test name ... FAILED` line each, followed by the
+failures the runner reports.
+
+    cargo test -p asmtest --test examples
+    cargo test -p asmtest --test examples -- --bless
+    cargo test -p asmtest --test examples -- fib
+
+`--bless` writes the current output to the `.expected` files instead of
+comparing. Other arguments only run the examples whose path contains them.
+Files that are only included by others should not end in `.asm`.
+*/
+
+use std::{env::args, fs, path::{Path, PathBuf}, process::ExitCode};
+
+use asmtest::diff;
+use assembler::Options;
+use emulator::{Machine, Step};
+use exe::Executable;
+
+/// Instructions to run before a program counts as stuck.
+const MAX_CYCLES: usize = 100_000;
+
+fn main() -> ExitCode {
+    let mut bless = false;
+    let mut filters = Vec::new();
+    for arg in args().skip(1) {
+        match arg.as_str() {
+            "--bless" => bless = true,
+            // flags cargo passes to every test binary
+            _ if arg.starts_with('-') => {},
+            _ => filters.push(arg),
+        }
+    }
+
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("../examples");
+    let mut programs = Vec::new();
+    find_programs(&root, &mut programs);
+    programs.retain(|path| filters.is_empty() || filters.iter().any(|filter| path.to_string_lossy().contains(filter.as_str())));
+
+    println!("\nrunning {} examples", programs.len());
+
+    let mut failed = Vec::new();
+    for program in &programs {
+        let name = program.strip_prefix(&root).unwrap_or(program).display().to_string();
+        let expected_path = program.with_extension("expected");
+        let output = run(program);
+
+        if bless {
+            match fs::write(&expected_path, &output) {
+                Ok(()) => println!("example {} ... blessed", name),
+                Err(e) => {
+                    println!("example {} ... FAILED", name);
+                    failed.push((name, format!("{}: {}", expected_path.display(), e)));
+                },
+            }
+            continue;
+        }
+
+        match fs::read_to_string(&expected_path) {
+            Ok(expected) if expected == output => println!("example {} ... ok", name),
+            Ok(expected) => {
+                println!("example {} ... FAILED", name);
+                let lines = |text: &str| text.lines().map(str::to_string).collect::<Vec<_>>();
+                failed.push((name, diff(&lines(&expected), &lines(&output))));
+            },
+            Err(_) => {
+                println!("example {} ... FAILED", name);
+                let expected_name = Path::new(&name).with_extension("expected");
+                failed.push((name, format!("{} is missing, run with --bless to create it", expected_name.display())));
+            },
+        }
+    }
+
+    if !failed.is_empty() {
+        println!("\nfailures:");
+        for (name, failure) in &failed {
+            println!("\n---- {} ----\n{}", name, failure);
+        }
+    }
+
+    println!(
+        "\nexample result: {}. {} passed; {} failed\n",
+        if failed.is_empty() { "ok" } else { "FAILED" },
+        programs.len() - failed.len(), failed.len()
+    );
+
+    if failed.is_empty() {
+        ExitCode::SUCCESS
+    }
+    else {
+        ExitCode::FAILURE
+    }
+}
+
+/// Collects the `.asm` files under `path`, sorted so the output is stable.
+fn find_programs(path: &Path, programs: &mut Vec<PathBuf>) {
+    let mut entries = fs::read_dir(path)
+        .unwrap_or_else(|e| panic!("{}: {}", path.display(), e))
+        .map(|entry| entry.expect("directory entry").path())
+        .collect::<Vec<_>>();
+    entries.sort();
+
+    for entry in entries {
+        if entry.is_dir() {
+            find_programs(&entry, programs);
+        }
+        else if entry.extension().is_some_and(|extension| extension == "asm") {
+            programs.push(entry);
+        }
+    }
+}
+
+/// Assembles and runs the program at `path` and returns what it printed,
+/// followed by the results of its `.test` blocks.
+fn run(path: &Path) -> String {
+    let assembled = match assembler::assemble_file(&path.to_string_lossy(), &Options::default()) {
+        Ok(assembled) => assembled,
+        Err(diagnostics) => {
+            let report = diagnostics.iter().map(ToString::to_string).collect::<Vec<_>>().join("\n");
+            return report.lines().map(|line| format!("error: {}\n", line)).collect();
+        },
+    };
+
+    let mut output = run_program(&assembled.program);
+    for test in &assembled.tests {
+        match asmtest::run(&assembled.program, test, None) {
+            Ok(()) => output.push_str(&format!("test {} ... ok\n", test.name)),
+            Err(failures) => {
+                output.push_str(&format!("test {} ... FAILED\n", test.name));
+                failures.iter().for_each(|failure| output.push_str(&format!("{}\n", failure)));
+            },
+        }
+    }
+    output
+}
+
+fn run_program(program: &Executable) -> String {
+    let mut output = String::new();
+    let mut machine = match Machine::new(program) {
+        Ok(machine) => machine,
+        Err(e) => return format!("error: {}\n", e),
+    };
+    for _ in 0..MAX_CYCLES {
+        match machine.step() {
+            Ok(Step::Running) => {},
+            Ok(Step::Output(value)) => output.push_str(&format!("{}\n", value)),
+            Ok(Step::Halted) => return output,
+            Err(e) => {
+                output.push_str(&format!("error: {}\n", e));
+                return output;
+            },
+        }
+    }
+    output.push_str(&format!("error: did not halt within {} cycles\n", MAX_CYCLES));
+    output
+}
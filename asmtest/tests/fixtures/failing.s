@@ -0,0 +1,22 @@
+; A test that fails on purpose, for the runner tests in tests/runner.rs. The
+; file does not end in .asm, so asmtest does not find it in the repository.
+
+    setrc VE @end
+    jump @countdown
+
+; prints V1 down to 1 and returns through VE
+@countdown
+    print V1
+    dec V1
+    ifeq V1 V0
+    jump @countdown
+    setpcr VE
+@end
+
+.test "countdown stops at 1"
+    .set V1 2
+    .call @countdown VE
+    .expect V1 1
+    .expect mem 0x10 1
+    .expect output 2
+.endtest
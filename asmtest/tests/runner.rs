@@ -0,0 +1,84 @@
+/*
+Tests for the `asmtest` command: how it reports failing tests, which files it
+takes tests from, and that the tests in the repository pass.
+*/
+
+use std::{env, fs, path::{Path, PathBuf}, process::{self, Command, Output}};
+
+fn asmtest(directory: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_asmtest"))
+        .current_dir(directory)
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+/// A directory of its own for a test, with `files` as (name, content) in it.
+fn directory(test: &str, files: &[(&str, &str)]) -> PathBuf {
+    let directory = env::temp_dir().join(format!("asmtest-{}-{}", test, process::id()));
+    let _ = fs::remove_dir_all(&directory);
+    fs::create_dir_all(&directory).unwrap();
+    for (name, content) in files {
+        fs::write(directory.join(name), content).unwrap();
+    }
+    directory
+}
+
+#[test]
+fn reports_failing_tests() {
+    let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    let output = asmtest(&fixtures, &["failing.s"]);
+    assert!(!output.status.success());
+
+    let expected = "\
+running 1 test
+test failing.s: countdown stops at 1 ... FAILED
+
+failures:
+
+---- failing.s: countdown stops at 1 (line 16) ----
+V1: expected 1, found 0
+[0x10]: expected 1, found 0
+output:
+    2
+  + 1
+
+failures:
+    failing.s: countdown stops at 1 (line 16)
+
+test result: FAILED. 0 passed; 1 failed; 0 filtered out
+";
+    assert_eq!(stdout(&output).trim(), expected.trim());
+}
+
+#[test]
+fn passes_in_the_repository() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("..");
+    let output = asmtest(&root, &[]);
+    assert!(output.status.success(), "{}{}", stdout(&output), String::from_utf8_lossy(&output.stderr));
+}
+
+#[test]
+fn only_runs_test_blocks() {
+    let directory = directory("blocks", &[
+        // `.test` in a comment or string is not a test block
+        ("comment.asm", "; see the .test blocks elsewhere\n.data\n.string \".test\"\n"),
+        // fragments without tests do not have to assemble
+        ("fragment.asm", "; .test\njump @elsewhere\n"),
+        ("broken.asm", "jump @elsewhere\n.test \"never runs\"\n.endtest\n"),
+        ("passing.asm", "setrc V1 1\n.test \"one\"\n.expect V1 1\n.endtest\n"),
+    ]);
+    let output = asmtest(&directory, &[]);
+    fs::remove_dir_all(&directory).unwrap();
+
+    assert!(!output.status.success());
+    let stdout = stdout(&output);
+    assert!(stdout.contains("running 1 test\n"), "{}", stdout);
+    assert!(stdout.contains("test ./passing.asm: one ... ok"), "{}", stdout);
+    assert!(stdout.contains("; 1 file did not assemble"), "{}", stdout);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("broken.asm"));
+}
@@ -0,0 +1,368 @@
+/*
+Constant expressions in operands, like `@N - 1` or `(@END + 2) << 1`.
+
+Operators from highest to lowest precedence:
+
+| Operator | Description |
+|-|-|
+| `~` `-` | Bitwise not and negation |
+| `*` `/` | Multiplication and division |
+| `+` `-` | Addition and subtraction |
+| `<<` `>>` | Shifts |
+| `<` `<=` `>` `>=` | Comparisons, 1 if true and 0 if false |
+| `==` `!=` | Equality, 1 if true and 0 if false |
+| `&` | Bitwise and |
+| `^` | Bitwise xor |
+| `\|` | Bitwise or |
+
+Values are numbers (`0x`, `0b` or decimal), character literals like `'A'` and
+`@label` references.
+
+Operands are separated by spaces, so an expression only spans several tokens
+where it can not end: after a binary operator, inside parentheses, or before
+a binary operator that stands alone like the `-` in `@N - 1`. `1 -1` is two
+operands, 1 and -1.
+
+In object files labels are relative to the start of their section or to an
+`.extern` symbol, so only expressions the linker can finish are allowed on
+them: adding or subtracting a constant, or the difference of two labels in
+the same section.
+*/
+
+use crate::lexer::unescape;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Operator {
+    Mul,
+    Div,
+    Add,
+    Sub,
+    Shl,
+    Shr,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+    And,
+    Xor,
+    Or,
+}
+
+impl Operator {
+    /// Operators with their precedence, longest symbol first so `<<` is not
+    /// read as two tokens.
+    const ALL: [(&'static str, Operator, u8); 15] = [
+        ("<<", Operator::Shl, 5),
+        (">>", Operator::Shr, 5),
+        ("<=", Operator::Le, 4),
+        (">=", Operator::Ge, 4),
+        ("==", Operator::Eq, 3),
+        ("!=", Operator::Ne, 3),
+        ("<", Operator::Lt, 4),
+        (">", Operator::Gt, 4),
+        ("*", Operator::Mul, 7),
+        ("/", Operator::Div, 7),
+        ("+", Operator::Add, 6),
+        ("-", Operator::Sub, 6),
+        ("&", Operator::And, 2),
+        ("^", Operator::Xor, 1),
+        ("|", Operator::Or, 0),
+    ];
+
+    fn apply(self, a: Value, b: Value) -> Result<Value, String> {
+        let base = match (self, a.base, b.base) {
+            (_, Base::Absolute, Base::Absolute) => Base::Absolute,
+            (Operator::Add, base, Base::Absolute) | (Operator::Add, Base::Absolute, base) => base,
+            (Operator::Sub, base, Base::Absolute) => base,
+            (Operator::Sub, a, b) if a == b => Base::Absolute,
+            _ => return Err(NOT_RELOCATABLE.to_string()),
+        };
+        Ok(Value { base, offset: self.apply_numbers(a.offset, b.offset)? })
+    }
+
+    fn apply_numbers(self, a: i64, b: i64) -> Result<i64, String> {
+        let result = match self {
+            Operator::Mul => a.checked_mul(b),
+            Operator::Div if b == 0 => return Err("Division by zero".to_string()),
+            Operator::Div => a.checked_div(b),
+            Operator::Add => a.checked_add(b),
+            Operator::Sub => a.checked_sub(b),
+            Operator::Shl => u32::try_from(b).ok().and_then(|b| a.checked_shl(b)),
+            Operator::Shr => u32::try_from(b).ok().and_then(|b| a.checked_shr(b)),
+            Operator::Lt => Some((a < b) as i64),
+            Operator::Le => Some((a <= b) as i64),
+            Operator::Gt => Some((a > b) as i64),
+            Operator::Ge => Some((a >= b) as i64),
+            Operator::Eq => Some((a == b) as i64),
+            Operator::Ne => Some((a != b) as i64),
+            Operator::And => Some(a & b),
+            Operator::Xor => Some(a ^ b),
+            Operator::Or => Some(a | b),
+        };
+        result.ok_or_else(|| "Overflow in expression".to_string())
+    }
+}
+
+const NOT_RELOCATABLE: &str =
+    "Addresses that are set by the linker can only be offset by a constant or subtracted from each other";
+
+/// What a value is relative to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Base {
+    Absolute,
+    /// The start of the code of the module, only in object files.
+    Code,
+    /// The start of the data of the module, only in object files.
+    Data,
+    /// An `.extern` symbol.
+    Extern(String),
+}
+
+/// The value of an operand, `offset` from `base`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Value {
+    pub base: Base,
+    pub offset: i64,
+}
+
+impl Value {
+    pub fn absolute(offset: i64) -> Self {
+        Self { base: Base::Absolute, offset }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Expr {
+    Number(i64),
+    /// A `@label` or `@constant` reference, without the `@`.
+    Symbol(String),
+    Not(Box<Expr>),
+    Neg(Box<Expr>),
+    Binary(Operator, Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    /// Evaluates the expression, looking up symbols with `lookup`.
+    pub fn evaluate(&self, lookup: &mut dyn FnMut(&str) -> Result<Value, String>) -> Result<Value, String> {
+        match self {
+            Expr::Number(n) => Ok(Value::absolute(*n)),
+            Expr::Symbol(name) => lookup(name),
+            Expr::Not(expr) => match expr.evaluate(lookup)? {
+                Value { base: Base::Absolute, offset } => Ok(Value::absolute(!offset)),
+                _ => Err(NOT_RELOCATABLE.to_string()),
+            },
+            Expr::Neg(expr) => match expr.evaluate(lookup)? {
+                Value { base: Base::Absolute, offset } => {
+                    offset.checked_neg().map(Value::absolute).ok_or_else(|| "Overflow in expression".to_string())
+                },
+                _ => Err(NOT_RELOCATABLE.to_string()),
+            },
+            Expr::Binary(operator, a, b) => operator.apply(a.evaluate(lookup)?, b.evaluate(lookup)?),
+        }
+    }
+
+    /// Adds the names of the symbols the expression refers to to `names`.
+    pub fn symbols<'a>(&'a self, names: &mut Vec<&'a str>) {
+        match self {
+            Expr::Number(_) => {},
+            Expr::Symbol(name) => names.push(name),
+            Expr::Not(expr) | Expr::Neg(expr) => expr.symbols(names),
+            Expr::Binary(_, a, b) => {
+                a.symbols(names);
+                b.symbols(names);
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(i64),
+    Symbol(String),
+    Operator(Operator),
+    Not,
+    Open,
+    Close,
+}
+
+/// Checks if an operand continues after `text`, either because it ends in
+/// the middle of an expression, or because `next` is a binary operator on
+/// its own or starts with one that can not be a unary minus.
+pub(crate) fn continues(text: &str, next: Option<&str>) -> bool {
+    let Ok(tokens) = lex(text) else {
+        return false;
+    };
+    let depth = tokens.iter().filter(|token| **token == Token::Open).count()
+        > tokens.iter().filter(|token| **token == Token::Close).count();
+    let open = matches!(tokens.last(), Some(Token::Operator(_) | Token::Not));
+    let operator = next.is_some_and(|next| match lex(next).as_deref() {
+        Ok([Token::Operator(_)]) => true,
+        Ok([Token::Operator(operator), ..]) => *operator != Operator::Sub,
+        _ => false,
+    });
+
+    depth || open || operator
+}
+
+/// Parses an operand expression.
+pub(crate) fn parse(text: &str) -> Result<Expr, String> {
+    let tokens = lex(text)?;
+    let mut position = 0;
+    let expr = parse_binary(&tokens, &mut position, 0)?;
+    match tokens.get(position) {
+        None => Ok(expr),
+        Some(token) => Err(format!("Unexpected {} in expression {}", describe(token), text)),
+    }
+}
+
+fn parse_binary(tokens: &[Token], position: &mut usize, min_precedence: u8) -> Result<Expr, String> {
+    let mut left = parse_unary(tokens, position)?;
+
+    while let Some(Token::Operator(operator)) = tokens.get(*position) {
+        let precedence = precedence(*operator);
+        if precedence < min_precedence {
+            break;
+        }
+        *position += 1;
+        let right = parse_binary(tokens, position, precedence + 1)?;
+        left = Expr::Binary(*operator, Box::new(left), Box::new(right));
+    }
+
+    Ok(left)
+}
+
+fn parse_unary(tokens: &[Token], position: &mut usize) -> Result<Expr, String> {
+    let token = tokens.get(*position).ok_or("Unexpected end of expression")?;
+    *position += 1;
+
+    match token {
+        Token::Number(n) => Ok(Expr::Number(*n)),
+        Token::Symbol(name) => Ok(Expr::Symbol(name.clone())),
+        Token::Not => Ok(Expr::Not(Box::new(parse_unary(tokens, position)?))),
+        Token::Operator(Operator::Sub) => Ok(Expr::Neg(Box::new(parse_unary(tokens, position)?))),
+        Token::Open => {
+            let expr = parse_binary(tokens, position, 0)?;
+            match tokens.get(*position) {
+                Some(Token::Close) => {
+                    *position += 1;
+                    Ok(expr)
+                },
+                _ => Err("Expected )".to_string()),
+            }
+        },
+        token => Err(format!("Unexpected {} in expression", describe(token))),
+    }
+}
+
+fn precedence(operator: Operator) -> u8 {
+    Operator::ALL
+        .iter()
+        .find(|(_, o, _)| *o == operator)
+        .map(|(.., precedence)| *precedence)
+        .expect("every operator has a precedence")
+}
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Number(n) => n.to_string(),
+        Token::Symbol(name) => format!("@{}", name),
+        Token::Operator(operator) => Operator::ALL
+            .iter()
+            .find(|(_, o, _)| o == operator)
+            .map(|(symbol, ..)| symbol.to_string())
+            .unwrap_or_default(),
+        Token::Not => "~".to_string(),
+        Token::Open => "(".to_string(),
+        Token::Close => ")".to_string(),
+    }
+}
+
+fn lex(text: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = text.trim_start();
+
+    while let Some(c) = rest.chars().next() {
+        let length = if let Some((symbol, operator, _)) = Operator::ALL
+            .iter()
+            .find(|(symbol, ..)| rest.starts_with(symbol))
+        {
+            tokens.push(Token::Operator(*operator));
+            symbol.len()
+        }
+        else if c == '~' || c == '(' || c == ')' {
+            tokens.push(match c {
+                '~' => Token::Not,
+                '(' => Token::Open,
+                _ => Token::Close,
+            });
+            1
+        }
+        else if c == '@' {
+            let length = symbol_length(&rest[1..]);
+            if length == 0 {
+                return Err(format!("Expected label name after @ in {}", text));
+            }
+            tokens.push(Token::Symbol(rest[1..=length].to_string()));
+            length + 1
+        }
+        else if c == '\'' {
+            let (value, length) = char_literal(rest)?;
+            tokens.push(Token::Number(value));
+            length
+        }
+        else if c.is_ascii_digit() {
+            let length = symbol_length(rest);
+            tokens.push(Token::Number(number(&rest[..length])?));
+            length
+        }
+        else {
+            return Err(format!("Unexpected '{}' in {}", c, text));
+        };
+
+        rest = rest[length..].trim_start();
+    }
+
+    Ok(tokens)
+}
+
+fn symbol_length(s: &str) -> usize {
+    s.find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '.'))
+        .unwrap_or(s.len())
+}
+
+fn number(s: &str) -> Result<i64, String> {
+    if let Some(hex) = s.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16).map_err(|_| format!("Not hex: {}", s))
+    }
+    else if let Some(binary) = s.strip_prefix("0b") {
+        i64::from_str_radix(binary, 2).map_err(|_| format!("Not binary: {}", s))
+    }
+    else {
+        s.parse().map_err(|_| format!("Not decimal: {}", s))
+    }
+}
+
+/// Reads a character literal like `'A'` or `'\n'` from the start of `s` and
+/// returns its value and length.
+fn char_literal(s: &str) -> Result<(i64, usize), String> {
+    let mut escaped = false;
+    let close = s
+        .char_indices()
+        .skip(1)
+        .find(|(_, c)| {
+            let close = *c == '\'' && !escaped;
+            escaped = *c == '\\' && !escaped;
+            close
+        })
+        .map(|(i, _)| i)
+        .ok_or_else(|| format!("Unterminated character literal {}", s))?;
+
+    let value = unescape(&s[1..close])?;
+    let mut chars = value.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if c.is_ascii() => Ok((c as i64, close + 1)),
+        _ => Err(format!("Character literal {} must be one ASCII character", &s[..=close])),
+    }
+}
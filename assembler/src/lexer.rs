@@ -0,0 +1,325 @@
+/*
+Lexing splits the source into lines of tokens. An `.include "file"` directive
+is replaced with the lines of the included file when the parser reaches it, so
+a file included in an inactive `.if` branch is never read. Included files are
+searched for next to the file that includes them and then in the include
+paths. A file is only included once, later includes of it are skipped, and a
+file that ends up including itself is an error.
+
+Comments are `; text` and `// text` to the end of the line, and `/* text */`
+which can span lines and nest. Comments separate tokens like whitespace and
+are ignored inside strings and character literals.
+*/
+
+use std::{collections::{HashMap, HashSet}, fmt::{self, Display}, fs, path::{Path, PathBuf}, rc::Rc};
+
+/// Where a line of source came from, used for error messages.
+#[derive(Debug, Clone)]
+pub(crate) struct Location {
+    /// Included file the line is in, `None` for the file being assembled.
+    pub file: Option<Rc<str>>,
+    /// Line in the source file, starting at 0, or `None` for a definition
+    /// from the command line.
+    pub line: Option<usize>,
+    /// Macro expansions the line came from as (macro name, call site),
+    /// innermost first.
+    pub expansions: Vec<(String, Location)>,
+    /// `.include` directives that lead to the file, innermost first.
+    pub includes: Rc<Vec<Location>>,
+}
+
+impl Location {
+    /// A definition from the command line, described by `option` alone.
+    pub fn command_line(option: String) -> Self {
+        Location {
+            file: Some(option.into()),
+            line: None,
+            expansions: Vec::new(),
+            includes: Rc::default(),
+        }
+    }
+
+    /// The file and line, like `line 3` or `lib.asm line 3`.
+    pub fn describe(&self) -> String {
+        match (&self.file, self.line) {
+            (Some(file), Some(line)) => format!("{} line {}", file, line + 1),
+            (None, Some(line)) => format!("line {}", line + 1),
+            (Some(option), None) => option.to_string(),
+            (None, None) => "the command line".to_string(),
+        }
+    }
+
+    /// Prefixes an error with this location.
+    pub fn error(&self, error: String) -> String {
+        self.diagnostic(error).to_string()
+    }
+
+    /// An error at this location, with the macro expansions and includes
+    /// that lead to it as notes.
+    pub fn diagnostic(&self, message: String) -> Diagnostic {
+        let file = self.file.as_deref().map(str::to_string);
+        let line = self.line.map(|line| line + 1);
+        let mut notes = self.expansions
+            .iter()
+            .map(|(name, call)| format!("in expansion of macro {} at {}", name, call.describe()))
+            .collect::<Vec<_>>();
+        notes.extend(self.includes.iter().map(|include| format!("included from {}", include.describe())));
+        Diagnostic { file, line, message, notes }
+    }
+}
+
+/// An error in the source. Displays like `lib.asm line 3: message` with one
+/// indented line per note.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// Included file the error is in, `None` for the source being assembled.
+    /// For a `-D` definition this is the option.
+    pub file: Option<String>,
+    /// Line starting at 1, `None` if the error is not about a line.
+    pub line: Option<usize>,
+    pub message: String,
+    /// Macro expansions and includes that lead to the line, innermost first.
+    pub notes: Vec<String>,
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.file, self.line) {
+            (Some(file), Some(line)) => write!(f, "{} line {}: ", file, line)?,
+            (None, Some(line)) => write!(f, "line {}: ", line)?,
+            (Some(option), None) => write!(f, "{}: ", option)?,
+            (None, None) => {},
+        }
+        write!(f, "{}", self.message)?;
+        self.notes.iter().try_for_each(|note| write!(f, "\n    {}", note))
+    }
+}
+
+impl From<String> for Diagnostic {
+    /// An error that is not about a line, like a file that can not be read.
+    fn from(message: String) -> Self {
+        Diagnostic { file: None, line: None, message, notes: Vec::new() }
+    }
+}
+
+/// A line of source split into tokens with the comments removed.
+#[derive(Debug, Clone)]
+pub(crate) struct Line {
+    pub tokens: Vec<String>,
+    pub location: Location,
+}
+
+/// Resolves `.include` directives, one at a time as the parser reaches them.
+pub(crate) struct Includes<'a> {
+    include_paths: &'a [PathBuf],
+    /// Directory of the file being assembled.
+    directory: PathBuf,
+    /// Canonical paths of the files by their name in `Location::file`.
+    files: HashMap<Option<Rc<str>>, PathBuf>,
+    /// Files that have been included, which are skipped if included again.
+    done: HashSet<PathBuf>,
+}
+
+impl<'a> Includes<'a> {
+    /// Includes for the file at `path`, or for source that is not in a file.
+    pub fn new(path: Option<&Path>, include_paths: &'a [PathBuf]) -> Self {
+        let mut includes = Includes {
+            include_paths,
+            directory: PathBuf::new(),
+            files: HashMap::new(),
+            done: HashSet::new(),
+        };
+        if let Some(path) = path {
+            includes.files.insert(None, fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf()));
+            includes.directory = path.parent().unwrap_or(Path::new("")).to_path_buf();
+        }
+        includes
+    }
+
+    /// Lexes the file included by the `.include` directive `line`. Returns no
+    /// lines if the file has been included before. Errors are located at the
+    /// directive or inside the included file.
+    pub fn include(&mut self, line: &Line) -> Result<Vec<Line>, Diagnostic> {
+        let location = &line.location;
+        let error = |e: String| location.diagnostic(e);
+
+        let name = match &line.tokens[1..] {
+            [name] => name
+                .strip_prefix('"')
+                .and_then(|name| name.strip_suffix('"'))
+                .map(unescape)
+                .ok_or_else(|| error(format!(".include expects a quoted file name, found {}", name)))?
+                .map_err(error)?,
+            _ => return Err(error(".include expects one file name".to_string())),
+        };
+
+        let directory = match &location.file {
+            Some(file) => Path::new(&**file).parent().unwrap_or(Path::new("")),
+            None => &self.directory,
+        };
+        let path = [directory]
+            .into_iter()
+            .chain(self.include_paths.iter().map(PathBuf::as_path))
+            .map(|directory| directory.join(&name))
+            .find(|path| path.is_file())
+            .ok_or_else(|| error(format!("Can not find {} to include", name)))?;
+        let canonical = fs::canonicalize(&path).map_err(|e| error(format!("{}: {}", path.display(), e)))?;
+
+        // the files that include the line, outermost first
+        let mut active: Vec<&PathBuf> = location.includes
+            .iter()
+            .rev()
+            .chain([location])
+            .filter_map(|include| self.files.get(&include.file))
+            .collect();
+        if let Some(start) = active.iter().position(|active| **active == canonical) {
+            active.push(&canonical);
+            let cycle = active[start..]
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect::<Vec<_>>()
+                .join(" -> ");
+            return Err(error(format!("Include cycle: {}", cycle)));
+        }
+        if !self.done.insert(canonical.clone()) {
+            return Ok(Vec::new());
+        }
+
+        let source = fs::read_to_string(&path).map_err(|e| error(format!("{}: {}", path.display(), e)))?;
+
+        let mut chain = vec![location.clone()];
+        chain.extend(location.includes.iter().cloned());
+
+        let file: Rc<str> = path.display().to_string().into();
+        self.files.insert(Some(file.clone()), canonical);
+        lex(&source, Some(file), Rc::new(chain))
+    }
+}
+
+/// Splits the source of a file into lines of tokens. `.include` directives
+/// are kept as lines for `Includes::include`. Lines that are empty after
+/// removing comments are dropped.
+pub(crate) fn lex(source: &str, file: Option<Rc<str>>, chain: Rc<Vec<Location>>) -> Result<Vec<Line>, Diagnostic> {
+    // depth of nested block comments and where the outermost one started
+    let mut comment = (0, None);
+    let mut lines = Vec::new();
+
+    for (line_number, line) in source.lines().enumerate() {
+        let location = Location {
+            file: file.clone(),
+            line: Some(line_number),
+            expansions: Vec::new(),
+            includes: chain.clone(),
+        };
+
+        let tokens = tokenize(line, &mut comment.0).map_err(|e| location.diagnostic(e))?;
+        match comment {
+            (0, _) => comment.1 = None,
+            (_, None) => comment.1 = Some(location.clone()),
+            _ => {},
+        }
+
+        if !tokens.is_empty() {
+            lines.push(Line { tokens, location });
+        }
+    }
+
+    if let (_, Some(start)) = comment {
+        return Err(start.diagnostic("Unterminated /* comment".to_string()));
+    }
+
+    Ok(lines)
+}
+
+/// Splits a line into tokens separated by whitespace and comments, keeping
+/// quoted strings and character literals as part of their token. `depth` is
+/// the number of block comments that are open, before and after the line.
+pub(crate) fn tokenize(line: &str, depth: &mut usize) -> Result<Vec<String>, String> {
+    let mut tokens = Vec::new();
+    let mut token = String::new();
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        let next = chars.peek().copied();
+
+        if *depth > 0 {
+            match (c, next) {
+                ('*', Some('/')) => {
+                    chars.next();
+                    *depth -= 1;
+                },
+                ('/', Some('*')) => {
+                    chars.next();
+                    *depth += 1;
+                },
+                _ => {},
+            }
+            continue;
+        }
+
+        match (c, next) {
+            ('/', Some('*')) => {
+                chars.next();
+                *depth += 1;
+            },
+            ('*', Some('/')) => {
+                return Err("*/ without /*".to_string());
+            },
+            (';', _) | ('/', Some('/')) => {
+                break;
+            },
+            ('"' | '\'', _) => {
+                token.push(c);
+                let mut escaped = false;
+                loop {
+                    let inner = chars.next().ok_or("Unterminated string")?;
+                    token.push(inner);
+                    if inner == c && !escaped {
+                        break;
+                    }
+                    escaped = inner == '\\' && !escaped;
+                }
+                continue;
+            },
+            (c, _) if !c.is_whitespace() => {
+                token.push(c);
+                continue;
+            },
+            _ => {},
+        }
+
+        // whitespace or a comment ends the token
+        if !token.is_empty() {
+            tokens.push(std::mem::take(&mut token));
+        }
+    }
+
+    if !token.is_empty() {
+        tokens.push(token);
+    }
+
+    Ok(tokens)
+}
+
+/// Resolves the escapes in the content of a string or character literal.
+pub(crate) fn unescape(s: &str) -> Result<String, String> {
+    let mut string = String::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            string.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => string.push('\n'),
+            Some('t') => string.push('\t'),
+            Some('0') => string.push('\0'),
+            Some('\\') => string.push('\\'),
+            Some('"') => string.push('"'),
+            Some('\'') => string.push('\''),
+            c => return Err(format!("Unknown escape: \\{}", c.map(String::from).unwrap_or_default())),
+        }
+    }
+
+    Ok(string)
+}
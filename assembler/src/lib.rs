@@ -32,12 +32,41 @@ Simplified instructionset based on CHIP-8
 
 */
 
-use std::{fs, str::SplitWhitespace, collections::HashMap, iter::Peekable};
+mod expr;
+mod lexer;
+mod lint;
+mod macros;
+pub mod output;
+mod symbols;
+mod unit_test;
 
+use std::{fs, collections::HashMap, iter::Peekable, path::{Path, PathBuf}, rc::Rc, vec::IntoIter};
+
+use exe::{object::{self, Export, Object, Relocation, Target}, Executable, Symbol, MEMORY_SIZE};
+
+use expr::{Base, Expr, Value};
+use lexer::Location;
+use macros::Macros;
+use symbols::Definition;
+use unit_test::TestBlock;
+
+pub use exe::{DebugInfo, SourceLine};
+pub use lexer::Diagnostic;
+pub use unit_test::Test;
+
+#[allow(clippy::enum_variant_names)]
 enum Label {
     Label(String),
     #[allow(non_camel_case_types)]
     u8(u8),
+    Expression(Box<Expr>),
+    /// A register operand, `V0` to `VF` or an `.alias`.
+    Register(u8),
+    /// An address in the code, for the jumps of pseudo-instructions.
+    Address(u8),
+    /// The register operand of `inc` and `dec`, which can not be VF since
+    /// they use VF as scratch register.
+    NotScratch(Box<Label>),
 }
 
 enum Instructions {
@@ -58,304 +87,1055 @@ enum Instructions {
     Xor(Label, Label),
     Not(Label),
 }
-pub fn run(path: &str) {
-    let t = fs::read_to_string(path)
-        .expect("Bad path");
 
-    let mut input = t
-        .split_whitespace()
-        .peekable();
+type Tokens = Peekable<IntoIter<String>>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Section {
+    Code,
+    Data,
+}
+
+/// An `.if` block that is being assembled.
+struct Condition {
+    location: Location,
+    /// Lines of the current branch are assembled.
+    active: bool,
+    /// A branch has been taken, or the whole block is inside an inactive
+    /// branch, so later branches are skipped.
+    taken: bool,
+    /// `.else` has been seen.
+    otherwise: bool,
+}
+
+/// Everything collected by the first pass over the source. Label references
+/// are resolved when encoding since they can point forward.
+struct Parser {
+    labels: HashMap<String, Definition>,
+    /// Instructions with the line they came from.
+    code: Vec<(Location, Instructions)>,
+    /// Initialized bytes of `memory` as (line, address, value).
+    data: Vec<(Location, usize, Label)>,
+    entry: Option<(Location, Label)>,
+    /// Labels placed in the code or data as (name, section, address), for
+    /// the lints.
+    placed: Vec<(String, Section, u8)>,
+    /// Symbols exported with `.global`.
+    globals: Vec<(Location, String)>,
+    /// Symbols imported with `.extern`, in the order of the object's imports.
+    externs: Vec<String>,
+    section: Section,
+    pc: u8,
+    data_pc: usize,
+    /// The last instruction was a skip, so the next one has to be a single
+    /// instruction.
+    after_skip: bool,
+    /// Open `.if` blocks, innermost last.
+    conditions: Vec<Condition>,
+    /// Register names defined with `.alias`.
+    aliases: HashMap<String, u8>,
+    /// `.test` blocks, checked and run separately from the program.
+    tests: Vec<TestBlock>,
+    /// Labels are relative to their section, for object files.
+    relocatable: bool,
+}
+
+/// The assembled code and data before they are packed into an executable or
+/// an object file.
+struct Assembly {
+    code: Vec<u8>,
+    data: Vec<u8>,
+    entry: Option<(Location, Value)>,
+    symbols: HashMap<String, Value>,
+    globals: Vec<(Location, String)>,
+    externs: Vec<String>,
+    relocations: Vec<Relocation>,
+    /// Resolved `.test` blocks, empty for object files.
+    tests: Vec<Test>,
+    debug: DebugInfo,
+}
+
+/// Resolves operands while encoding. Operands that depend on where the linker
+/// places a module are written as 0 with a relocation.
+struct Encoder<'a> {
+    symbols: &'a HashMap<String, Value>,
+    externs: &'a [String],
+    relocatable: bool,
+    /// Register fields only take register operands.
+    strict: bool,
+    relocations: Vec<Relocation>,
+}
+
+/// Settings for assembling a file.
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// Directories searched for `.include` files after the directory of the
+    /// including file.
+    pub include_paths: Vec<PathBuf>,
+    /// Constants defined before the source as (name, value), like
+    /// `-D NAME=value` on the command line.
+    pub defines: Vec<(String, String)>,
+    /// Reject plain numbers as register operands, so registers have to be
+    /// written `V0` to `VF` or with an `.alias`.
+    pub strict: bool,
+}
+
+/// A program assembled from source.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Assembled {
+    /// Code, data, entry point and symbol table.
+    pub program: Executable,
+    /// Source lines and labels of the code, kept out of `program` unless it
+    /// is written with `-g`.
+    pub debug: DebugInfo,
+    /// The `.test` blocks of the source.
+    pub tests: Vec<Test>,
+}
+
+/// Assembles the file at `path` and returns the raw binary. A raw binary only
+/// holds code, so programs with a `.data` section are an error.
+pub fn run(path: &str) -> Result<Vec<u8>, String> {
+    let program = assemble_file(path, &Options::default())
+        .map_err(|diagnostics| diagnostics.iter().map(Diagnostic::to_string).collect::<Vec<_>>().join("\n"))?
+        .program;
+    if !program.data.is_empty() {
+        return Err(format!("{}: the program has a .data section, which a raw binary can not hold", path));
+    }
+    Ok(program.code)
+}
+
+/// Assembles `source` into a program with its debug info. `.include` files
+/// are searched for in the include paths. Every line with an error is
+/// reported, but errors while encoding are only looked for if the first pass
+/// over the source succeeds.
+pub fn assemble(source: &str, options: &Options) -> Result<Assembled, Vec<Diagnostic>> {
+    assemble_source(source, None, options)
+}
+
+/// Assembles the file at `path` like `assemble`. Included files are also
+/// searched for next to it.
+pub fn assemble_file(path: &str, options: &Options) -> Result<Assembled, Vec<Diagnostic>> {
+    assemble_source(&read(path)?, Some(Path::new(path)), options)
+}
+
+fn assemble_source(source: &str, path: Option<&Path>, options: &Options) -> Result<Assembled, Vec<Diagnostic>> {
+    let mut assembly = assemble_lines(source, path, options, false)?;
+    let mut debug = std::mem::take(&mut assembly.debug);
+    debug.source = path.map(|path| path.display().to_string());
+    let tests = std::mem::take(&mut assembly.tests);
+    let program = executable(assembly).map_err(|e| vec![e])?;
+    Ok(Assembled { program, debug, tests })
+}
+
+fn executable(assembly: Assembly) -> Result<Executable, Diagnostic> {
+    let entry = match assembly.entry {
+        Some((location, value)) => absolute(value, 8).map_err(|e| location.diagnostic(e))?,
+        None => 0,
+    };
+
+    // negative constants are stored as two's complement like operands, the
+    // lint warns about the ones that do not fit in 8 bits
+    let mut symbols = assembly.symbols
+        .iter()
+        .filter(|(_, value)| value.base == Base::Absolute)
+        .filter_map(|(name, value)| Some(Symbol { name: name.clone(), value: fit(value.offset, 8).ok()? }))
+        .collect::<Vec<_>>();
+    symbols.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Ok(Executable {
+        entry,
+        code: assembly.code,
+        data: assembly.data,
+        symbols,
+        debug: None,
+    })
+}
+
+/// Assembles the file at `path` into a relocatable object file for the
+/// linker. Labels are relative to the module, `.global` symbols are exported
+/// and `.extern` symbols are imported.
+pub fn assemble_object(path: &str, options: &Options) -> Result<Object, Vec<Diagnostic>> {
+    let assembly = assemble_lines(&read(path)?, Some(Path::new(path)), options, true)?;
+    let error = |location: &Location, e: String| vec![location.diagnostic(e)];
+
+    let entry = match assembly.entry {
+        Some((location, Value { base: Base::Code, offset })) => {
+            Some(fit(offset, 8).map_err(|e| error(&location, e))?)
+        },
+        Some((location, _)) => {
+            return Err(error(&location, ".entry of an object file has to be a label in the code".to_string()));
+        },
+        None => None,
+    };
+
+    let mut exports = Vec::new();
+    for (location, name) in assembly.globals {
+        let value = assembly.symbols
+            .get(&name)
+            .ok_or_else(|| error(&location, format!("@{} is .global but never defined", name)))?;
+        let base = match value.base {
+            Base::Absolute => object::Base::Absolute,
+            Base::Code => object::Base::Code,
+            Base::Data => object::Base::Data,
+            Base::Extern(_) => {
+                return Err(error(&location, format!("@{} is .extern and can not be .global", name)));
+            },
+        };
+        let value = i16::try_from(value.offset)
+            .map_err(|_| error(&location, format!("@{} = {} does not fit in 16 bits", name, value.offset)))?;
+        exports.push(Export { name, base, value });
+    }
+
+    Ok(Object {
+        entry,
+        code: assembly.code,
+        data: assembly.data,
+        exports,
+        imports: assembly.externs,
+        relocations: assembly.relocations,
+    })
+}
+
+/// Checks if `source` has a `.test` block without assembling it, for files
+/// that do not assemble on their own. `.test` in comments and strings does
+/// not count.
+pub fn has_tests(source: &str) -> bool {
+    lexer::lex(source, None, Rc::default())
+        .is_ok_and(|lines| lines.iter().any(|line| line.tokens.first().is_some_and(|token| token == ".test")))
+}
+
+/// Checks the file at `path` for code that assembles but is likely wrong and
+/// returns a warning for each problem, located like errors.
+pub fn lint_file(path: &str, options: &Options) -> Result<Vec<String>, Vec<Diagnostic>> {
+    let (parser, symbols) = parse(&read(path)?, Some(Path::new(path)), options, false)?;
+    let encoder = Encoder {
+        symbols: &symbols,
+        externs: &parser.externs,
+        relocatable: false,
+        strict: options.strict,
+        relocations: Vec::new(),
+    };
+    Ok(lint::lint(&parser, &encoder))
+}
+
+fn read(path: &str) -> Result<String, Vec<Diagnostic>> {
+    fs::read_to_string(path).map_err(|e| vec![e.to_string().into()])
+}
+
+/// Runs the first pass over `source`, read from `path` if it is a file, and
+/// resolves its symbols.
+fn parse(
+    source: &str,
+    path: Option<&Path>,
+    options: &Options,
+    relocatable: bool,
+) -> Result<(Parser, HashMap<String, Value>), Vec<Diagnostic>> {
+    let mut parser = Parser {
+        labels: HashMap::new(),
+        code: Vec::new(),
+        data: Vec::new(),
+        entry: None,
+        placed: Vec::new(),
+        globals: Vec::new(),
+        externs: Vec::new(),
+        section: Section::Code,
+        pc: 0,
+        data_pc: 0,
+        after_skip: false,
+        conditions: Vec::new(),
+        aliases: HashMap::new(),
+        tests: Vec::new(),
+        relocatable,
+    };
+
+    for (name, value) in &options.defines {
+        let location = Location::command_line(format!("-D {}={}", name, value));
+        lexer::tokenize(value, &mut 0)
+            .and_then(|tokens| get_label(&mut tokens.into_iter().peekable()))
+            .and_then(|value| parser.define(&location, name, value, Base::Absolute))
+            .map_err(|e| vec![location.diagnostic(e)])?;
+    }
+
+    // includes and macros are expanded when they are reached, after the
+    // `.if` blocks around them are evaluated
+    let mut includes = lexer::Includes::new(path, &options.include_paths);
+    let mut macros = Macros::default();
+    // lines still to parse, the next one last
+    let mut pending = lexer::lex(source, None, Rc::default()).map_err(|e| vec![e])?;
+    pending.reverse();
 
-    let mut labels = HashMap::new();
+    let mut diagnostics = Vec::new();
+    while let Some(line) = pending.pop() {
+        let lines = if macros.defining() {
+            macros.body(line).map(|()| Vec::new())
+        }
+        else {
+            match parser.condition(&line.location, &line.tokens) {
+                Ok(true) => continue,
+                Ok(false) => {},
+                Err(e) => {
+                    diagnostics.push(line.location.diagnostic(e));
+                    continue;
+                },
+            }
+            match line.tokens[0].as_str() {
+                ".include" => includes.include(&line),
+                ".macro" => macros.define(&line).map(|()| Vec::new()),
+                ".endm" => macros.body(line).map(|()| Vec::new()),
+                _ => match macros.expand(&line) {
+                    Ok(None) => {
+                        if let Err(e) = parser.line(&line.location, line.tokens) {
+                            diagnostics.push(line.location.diagnostic(e));
+                        }
+                        continue;
+                    },
+                    expansion => expansion.map(Option::unwrap_or_default),
+                },
+            }
+        };
 
-    let mut code = Vec::new();
+        // errors in includes and macros stop the assembly
+        match lines {
+            Ok(lines) => pending.extend(lines.into_iter().rev()),
+            Err(e) => {
+                diagnostics.push(e);
+                return Err(diagnostics);
+            },
+        }
+    }
+    if let Err(e) = macros.finish() {
+        diagnostics.push(e);
+    }
+    if let Some(condition) = parser.conditions.last() {
+        diagnostics.push(condition.location.diagnostic(".if without .endif".to_string()));
+    }
+    if let Some(location) = parser.tests.last().and_then(TestBlock::unclosed) {
+        diagnostics.push(location.diagnostic(".test without .endtest".to_string()));
+    }
+    if !diagnostics.is_empty() {
+        return Err(diagnostics);
+    }
 
-    let mut comment = false;
+    let symbols = symbols::resolve_all(&parser.labels).map_err(|e| vec![e])?;
+    Ok((parser, symbols))
+}
 
-    let mut pc = 0;
+fn assemble_lines(
+    source: &str,
+    path: Option<&Path>,
+    options: &Options,
+    relocatable: bool,
+) -> Result<Assembly, Vec<Diagnostic>> {
+    let (parser, symbols) = parse(source, path, options, relocatable)?;
+    let Parser { code, data, entry, placed, globals, externs, tests, .. } = parser;
 
-    // lexer
-    loop {
-        let token = if let Some(s) = input.next() { s }
-        else { break };
-            
-        if token.starts_with("/*") {
-            comment = true;
-            continue;
+    let mut encoder = Encoder {
+        symbols: &symbols,
+        externs: &externs,
+        relocatable,
+        strict: options.strict,
+        relocations: Vec::new(),
+    };
+
+    let mut diagnostics = Vec::new();
+    let mut binary = Vec::new();
+    let mut debug = DebugInfo::default();
+
+    // tokens to binary
+    for (location, token) in code {
+        if let Err(e) = encode(token, &mut encoder, &mut binary) {
+            diagnostics.push(location.diagnostic(e));
+        }
+        // the file of a command line definition is the option, which is not a source file
+        let file = location.line.and(location.file.as_deref().map(str::to_string));
+        debug.lines.push(SourceLine { file, line: location.line.map(|line| line + 1) });
+    }
+    debug.labels = placed
+        .into_iter()
+        .filter(|(_, section, _)| *section == Section::Code)
+        .map(|(name, _, pc)| (name, pc))
+        .collect();
+    debug.labels.sort_by_key(|(_, pc)| *pc);
+
+    let mut memory = vec![None; MEMORY_SIZE];
+    for (location, address, label) in data {
+        match encoder.u8(label, object::Section::Data, address) {
+            Ok(value) => {
+                if memory[address].replace(value).is_some() {
+                    diagnostics.push(location.diagnostic(format!("Data overlaps at address 0x{:02X}", address)));
+                }
+            },
+            Err(e) => diagnostics.push(location.diagnostic(e)),
         }
-        if token.ends_with("*/") {
-            comment = false;
-            continue;
+    }
+    let data_size = memory.iter().rposition(Option::is_some).map_or(0, |i| i + 1);
+    let data: Vec<u8> = memory[..data_size].iter().map(|byte| byte.unwrap_or(0)).collect();
+
+    let entry = entry.and_then(|(location, label)| match encoder.value(&label) {
+        Ok(value) => Some((location, value)),
+        Err(e) => {
+            diagnostics.push(location.diagnostic(e));
+            None
+        },
+    });
+
+    // tests use absolute addresses, so they only make sense for a whole program
+    let mut resolved = Vec::new();
+    if !relocatable {
+        for test in &tests {
+            match test.resolve(&mut encoder) {
+                Ok(test) => resolved.push(test),
+                Err(e) => diagnostics.push(e),
+            }
         }
-        if comment {
-            continue;
+    }
+
+    if !diagnostics.is_empty() {
+        return Err(diagnostics);
+    }
+
+    let relocations = encoder.relocations;
+
+    Ok(Assembly {
+        code: binary,
+        data,
+        entry,
+        symbols,
+        globals,
+        externs,
+        relocations,
+        tests: resolved,
+        debug,
+    })
+}
+
+impl Parser {
+    fn line(&mut self, location: &Location, tokens: Vec<String>) -> Result<(), String> {
+        if self.test_line(location, &tokens)? {
+            return Ok(());
         }
 
-        if token.starts_with("@") {
-            if input.peek() == Some(&"=") {
-                input.next();
-                let value = get_label(&mut input);
-                labels.insert(token.replace("@", ""), value);
+        let mut input = tokens.into_iter().peekable();
+
+        while let Some(token) = input.next() {
+            if token.starts_with("@") {
+                if input.peek().map(String::as_str) == Some("=") {
+                    input.next();
+                    let value = self.operand(&mut input)?;
+                    self.define(location, &token, value, Base::Absolute)?;
+                    continue;
+                }
+                else {
+                    let address = match self.section {
+                        Section::Code => self.pc,
+                        Section::Data => data_address(self.data_pc)?,
+                    };
+                    let base = match (self.relocatable, &self.section) {
+                        (false, _) => Base::Absolute,
+                        (true, Section::Code) => Base::Code,
+                        (true, Section::Data) => Base::Data,
+                    };
+                    self.define(location, &token, Label::u8(address), base)?;
+                    self.placed.push((token.replace("@", ""), self.section, address));
+                }
+
                 continue;
             }
-            else {
-                labels.insert(token.replace("@", ""), Label::u8(pc));
+
+            if token.starts_with(".") {
+                self.directive(location, &token, &mut input)?;
+                continue;
             }
 
-            continue;
+            let instruction = match token.as_str() {
+                "jump" => Instructions::Jump(
+                    self.operand(&mut input)?
+                ),
+                "ifeq" => Instructions::IfEq(
+                    self.operand(&mut input)?,
+                    self.operand(&mut input)?
+                ),
+                "ifneq" => Instructions::IfNeq(
+                    self.operand(&mut input)?,
+                    self.operand(&mut input)?
+                ),
+                "ifle" => Instructions::IfLe(
+                    self.operand(&mut input)?,
+                    self.operand(&mut input)?
+                ),
+                "setrr" => Instructions::SetRr(
+                    self.operand(&mut input)?,
+                    self.operand(&mut input)?
+                ),
+                "setrpc" => Instructions::SetRpc(
+                    self.operand(&mut input)?
+                ),
+                "setrm" => Instructions::SetRm(
+                    self.operand(&mut input)?,
+                    self.operand(&mut input)?
+                ),
+                "setrc" => Instructions::SetRc(
+                    self.operand(&mut input)?,
+                    self.operand(&mut input)?
+                ),
+                "setpcr" => Instructions::SetPcr(
+                    self.operand(&mut input)?
+                ),
+                "setmr" => Instructions::SetMr(
+                    self.operand(&mut input)?,
+                    self.operand(&mut input)?
+                ),
+                "add" => Instructions::Add(
+                    self.operand(&mut input)?,
+                    self.operand(&mut input)?
+                ),
+                "sub" => Instructions::Sub(
+                    self.operand(&mut input)?,
+                    self.operand(&mut input)?
+                ),
+                "and" => Instructions::And(
+                    self.operand(&mut input)?,
+                    self.operand(&mut input)?
+                ),
+                "or" => Instructions::Or(
+                    self.operand(&mut input)?,
+                    self.operand(&mut input)?
+                ),
+                "xor" => Instructions::Xor(
+                    self.operand(&mut input)?,
+                    self.operand(&mut input)?
+                ),
+                "not" => Instructions::Not(
+                    self.operand(&mut input)?
+                ),
+                _ => {
+                    let instructions = self.pseudo(&token, &mut input)?
+                        .ok_or_else(|| format!("Unknown instruction or extra operand: {}", token))?;
+                    self.push_code(location, &token, instructions)?;
+                    continue;
+                }
+            };
+
+            self.push_code(location, &token, vec![instruction])?;
         }
 
-        match token {
-            "jump" => {
-                code.push(Instructions::Jump(
-                    get_label(&mut input)
-                ));
+        Ok(())
+    }
+
+    /// Handles the conditional assembly directives. Returns `true` if the line
+    /// was one of them or is in a branch that is skipped.
+    fn condition(&mut self, location: &Location, tokens: &[String]) -> Result<bool, String> {
+        let active = self.conditions.last().is_none_or(|condition| condition.active);
+
+        match tokens[0].as_str() {
+            directive @ (".if" | ".ifdef" | ".ifndef") => {
+                // a condition that fails to evaluate still opens a block, with
+                // all its branches skipped, so its .elif, .else and .endif match
+                let value = if active { self.test(directive, tokens[1..].to_vec()) } else { Ok(false) };
+                let taken = value.as_ref().map_or(true, |value| *value || !active);
+                self.conditions.push(Condition {
+                    location: location.clone(),
+                    active: value.as_ref().is_ok_and(|value| *value),
+                    taken,
+                    otherwise: false,
+                });
+                value?;
             },
-            "ifeq" => {
-                code.push(Instructions::IfEq(
-                    get_label(&mut input), 
-                    get_label(&mut input)
-                ));
+            ".elif" => {
+                let condition = self.conditions.last().ok_or(".elif without .if")?;
+                if condition.otherwise {
+                    return Err(".elif after .else".to_string());
+                }
+                let value = if condition.taken { Ok(false) } else { self.test(".elif", tokens[1..].to_vec()) };
+                let condition = self.conditions.last_mut().ok_or(".elif without .if")?;
+                condition.active = value.as_ref().is_ok_and(|value| *value);
+                condition.taken |= value.as_ref().map_or(true, |value| *value);
+                value?;
             },
-            "ifneq" => {
-                code.push(Instructions::IfNeq(
-                    get_label(&mut input), 
-                    get_label(&mut input)
-                ));
+            ".else" => {
+                let condition = self.conditions.last_mut().ok_or(".else without .if")?;
+                if condition.otherwise {
+                    return Err(".else after .else".to_string());
+                }
+                condition.active = !condition.taken;
+                condition.taken = true;
+                condition.otherwise = true;
             },
-            "ifle" => {
-                code.push(Instructions::IfLe(
-                    get_label(&mut input), 
-                    get_label(&mut input)
-                ));
+            ".endif" => {
+                self.conditions.pop().ok_or(".endif without .if")?;
             },
-            "setrr" => {
-                code.push(Instructions::SetRr(
-                    get_label(&mut input), 
-                    get_label(&mut input)
-                ));
+            _ => return Ok(!active),
+        }
+
+        Ok(true)
+    }
+
+    /// Evaluates the condition of `.if`, `.elif`, `.ifdef` or `.ifndef`.
+    fn test(&self, directive: &str, args: Vec<String>) -> Result<bool, String> {
+        let mut input = args.into_iter().peekable();
+        let value = match directive {
+            ".ifdef" | ".ifndef" => {
+                let name = input.next().ok_or_else(|| format!("{} expects a symbol", directive))?;
+                self.labels.contains_key(name.trim_start_matches('@')) == (directive == ".ifdef")
             },
-            "setrpc" => {
-                code.push(Instructions::SetRpc(
-                    get_label(&mut input)
-                ));
+            _ => {
+                let value = symbols::evaluate_defined(&get_label(&mut input)?, &self.labels)
+                    .map_err(|e| format!("{} (operands of {} have to be defined before it)", e, directive))?;
+                match value.base {
+                    Base::Absolute => value.offset != 0,
+                    _ => return Err(format!("{} needs a constant, not an address", directive)),
+                }
             },
-            "setrm" => {
-                code.push(Instructions::SetRm(
-                    get_label(&mut input),
-                    get_label(&mut input)
-                ));
+        };
+        if let Some(token) = input.next() {
+            return Err(format!("Unexpected {} after the condition of {}", token, directive));
+        }
+        Ok(value)
+    }
+
+    /// Expands a pseudo-instruction into real instructions, or returns `None`
+    /// if `token` is not one. `inc` and `dec` use VF as scratch register.
+    fn pseudo(&self, token: &str, input: &mut Tokens) -> Result<Option<Vec<Instructions>>, String> {
+        // address `offset` instructions after the start of the expansion
+        let address = |offset: u8| {
+            self.pc
+                .checked_add(offset)
+                .map(Label::Address)
+                .ok_or_else(|| "Program does not fit in 256 instructions".to_string())
+        };
+
+        let instructions = match token {
+            "nop" => vec![
+                Instructions::SetRr(Label::Register(0), Label::Register(0)),
+            ],
+            "mov" => {
+                let vx = self.operand(input)?;
+                match self.operand(input)? {
+                    vy @ Label::Register(_) => vec![Instructions::SetRr(vx, vy)],
+                    value => vec![Instructions::SetRc(vx, value)],
+                }
             },
-            "setrc" => {
-                code.push(Instructions::SetRc(
-                    get_label(&mut input), 
-                    get_label(&mut input)
-                ));
+            "clr" => vec![
+                Instructions::SetRc(self.operand(input)?, Label::u8(0)),
+            ],
+            "print" => vec![
+                Instructions::SetMr(self.operand(input)?, Label::u8(0xFF)),
+            ],
+            "inc" | "dec" => {
+                let vx = Label::NotScratch(Box::new(self.operand(input)?));
+                let scratch = Label::Register(0xF);
+                vec![
+                    Instructions::SetRc(Label::Register(0xF), Label::u8(1)),
+                    if token == "inc" { Instructions::Add(vx, scratch) } else { Instructions::Sub(vx, scratch) },
+                ]
             },
-            "setpcr" => {
-                code.push(Instructions::SetPcr(
-                    get_label(&mut input)
-                ));
+            "ifge" => {
+                let vx = self.operand(input)?;
+                vec![Instructions::IfLe(self.operand(input)?, vx)]
             },
-            "setmr" => {
-                code.push(Instructions::SetMr(
-                    get_label(&mut input), 
-                    get_label(&mut input)
-                ));
+            "ifgt" => vec![
+                Instructions::IfLe(self.operand(input)?, self.operand(input)?),
+                Instructions::Jump(address(3)?),
+            ],
+            "beq" | "bne" | "blt" | "bgt" | "ble" | "bge" => {
+                let vx = self.operand(input)?;
+                let vy = self.operand(input)?;
+                let target = Instructions::Jump(self.operand(input)?);
+                match token {
+                    "beq" => vec![Instructions::IfNeq(vx, vy), target],
+                    "bne" => vec![Instructions::IfEq(vx, vy), target],
+                    "bgt" => vec![Instructions::IfLe(vx, vy), target],
+                    "blt" => vec![Instructions::IfLe(vy, vx), target],
+                    "ble" => vec![Instructions::IfLe(vx, vy), Instructions::Jump(address(3)?), target],
+                    _ => vec![Instructions::IfLe(vy, vx), Instructions::Jump(address(3)?), target],
+                }
             },
-            "add" => {
-                code.push(Instructions::Add(
-                    get_label(&mut input), 
-                    get_label(&mut input)
-                ));
+            _ => return Ok(None),
+        };
+
+        Ok(Some(instructions))
+    }
+
+    /// Adds the instructions of one mnemonic to the code.
+    fn push_code(&mut self, location: &Location, token: &str, instructions: Vec<Instructions>) -> Result<(), String> {
+        if self.section == Section::Data {
+            return Err(format!("{} is not allowed in the .data section", token));
+        }
+        if self.after_skip && instructions.len() > 1 {
+            return Err(format!(
+                "{} expands to {} instructions, but the skip before it only skips the first",
+                token, instructions.len()
+            ));
+        }
+
+        self.after_skip = matches!(token, "ifeq" | "ifneq" | "ifle" | "ifge" | "ifgt");
+        self.pc = u8::try_from(self.pc as usize + instructions.len())
+            .map_err(|_| "Program does not fit in 256 instructions".to_string())?;
+        self.code.extend(instructions.into_iter().map(|instruction| (location.clone(), instruction)));
+        Ok(())
+    }
+
+    fn directive(&mut self, location: &Location, directive: &str, input: &mut Tokens) -> Result<(), String> {
+        match directive {
+            ".code" => {
+                self.section = Section::Code;
+                return Ok(());
             },
-            "sub" => {
-                code.push(Instructions::Sub(
-                    get_label(&mut input), 
-                    get_label(&mut input)
-                ));
+            ".data" => {
+                self.section = Section::Data;
+                return Ok(());
             },
-            "and" => {
-                code.push(Instructions::And(
-                    get_label(&mut input), 
-                    get_label(&mut input)
-                ));
+            ".entry" => {
+                self.entry = Some((location.clone(), self.operand(input)?));
+                return Ok(());
             },
-            "or" => {
-                code.push(Instructions::Or(
-                    get_label(&mut input), 
-                    get_label(&mut input)
-                ));
+            ".alias" => {
+                let name = input.next().ok_or(".alias expects a name")?;
+                if !name.chars().all(|c| c.is_alphanumeric() || c == '_') || register(&name).is_some() {
+                    return Err(format!("Invalid alias name: {}", name));
+                }
+                let target = input
+                    .next()
+                    .as_deref()
+                    .and_then(register)
+                    .ok_or(".alias expects a name and a register like V2")?;
+                if self.aliases.insert(name.clone(), target).is_some() {
+                    return Err(format!("Alias {} is already defined", name));
+                }
+                return Ok(());
             },
-            "xor" => {
-                code.push(Instructions::Xor(
-                    get_label(&mut input), 
-                    get_label(&mut input)
-                ));
+            ".global" => {
+                let name = input.next().ok_or(".global expects a symbol")?;
+                self.globals.push((location.clone(), name.replace("@", "")));
+                return Ok(());
             },
-            "not" => {
-                code.push(Instructions::Not(
-                    get_label(&mut input)
-                ));
+            ".extern" => {
+                let token = input.next().ok_or(".extern expects a symbol")?;
+                let name = token.replace("@", "");
+                self.define(location, &token, Label::u8(0), Base::Extern(name.clone()))?;
+                self.externs.push(name);
+                return Ok(());
             },
+            ".byte" | ".string" | ".fill" | ".org" => {},
             _ => {
-                continue;
+                return Err(format!("Unknown directive: {}", directive));
             }
         }
 
-        pc += 1;
-    }
-
-    let mut binary = Vec::new();
+        if self.section != Section::Data {
+            return Err(format!("{} is only allowed in the .data section", directive));
+        }
 
-    // tokens to binary
-    for token in code {
-        match token {
-            Instructions::Jump(label) => {
-                binary.push(0x00);
-                binary.push(label_to_u8(label, &labels));
-            },
-            Instructions::IfEq(label1, label2) => {
-                binary.push(0x10);
-                binary.push(u4u4_to_u8(
-                    label_to_u8(label1, &labels), 
-                    label_to_u8(label2, &labels)
-                ));
-            },
-            Instructions::IfNeq(label1, label2) => {
-                binary.push(0x20);
-                binary.push(u4u4_to_u8(
-                    label_to_u8(label1, &labels), 
-                    label_to_u8(label2, &labels)
-                ));
-            },
-            Instructions::IfLe(label1, label2) => {
-                binary.push(0x30);
-                binary.push(u4u4_to_u8(
-                    label_to_u8(label1, &labels), 
-                    label_to_u8(label2, &labels)
-                ));
-            },
-            Instructions::SetRr(label1, label2) => {
-                binary.push(0x40);
-                binary.push(u4u4_to_u8(
-                    label_to_u8(label1, &labels), 
-                    label_to_u8(label2, &labels)
-                ));
+        match directive {
+            ".byte" => {
+                while input.peek().is_some() {
+                    self.push_data(location, self.operand(input)?)?;
+                }
             },
-            Instructions::SetRpc(label) => {
-                binary.push(0x41);
-                binary.push(label_to_u8(label, &labels));
+            ".string" => {
+                let string = input.next().ok_or("Expected string")?;
+                for byte in unquote(&string)?.bytes().chain([0]) {
+                    self.push_data(location, Label::u8(byte))?;
+                }
             },
-            Instructions::SetRm(label1, label2) => {
-                binary.push(0x50 | label_to_u8(label1, &labels));
-                binary.push(label_to_u8(label2, &labels));
+            ".fill" => {
+                let count = self.evaluate_unsigned_now(directive, self.operand(input)?)?;
+                let value = if input.peek().is_some() {
+                    self.evaluate_now(directive, self.operand(input)?)?
+                }
+                else {
+                    0
+                };
+                for _ in 0..count {
+                    self.push_data(location, Label::u8(value))?;
+                }
             },
-            Instructions::SetRc(label1, label2) => {
-                binary.push(0x60 | label_to_u8(label1, &labels));
-                binary.push(label_to_u8(label2, &labels));
+            ".org" => {
+                self.data_pc = self.evaluate_unsigned_now(directive, self.operand(input)?)?;
             },
-            Instructions::SetPcr(label) => {
-                binary.push(0x70);
-                binary.push(label_to_u8(label, &labels));
+            _ => unreachable!(),
+        }
+
+        Ok(())
+    }
+
+    fn define(&mut self, location: &Location, token: &str, value: Label, base: Base) -> Result<(), String> {
+        let name = token.replace("@", "");
+        if let Some(previous) = self.labels.get(&name) {
+            return Err(format!("@{} is already defined at {}", name, previous.location.describe()));
+        }
+        let order = self.labels.len();
+        self.labels.insert(name, Definition { value, base, location: location.clone(), order });
+        Ok(())
+    }
+
+    /// Reads one operand, which can also be a register name or an alias.
+    fn operand(&self, code: &mut Tokens) -> Result<Label, String> {
+        let register = code
+            .peek()
+            .and_then(|token| self.aliases.get(token).copied().or_else(|| register(token)));
+        if let Some(register) = register {
+            code.next();
+            return Ok(Label::Register(register));
+        }
+        get_label(code)
+    }
+
+    /// Resolves an operand that is needed during the first pass, so it can
+    /// only use symbols that are defined above it.
+    fn evaluate_now(&self, directive: &str, label: Label) -> Result<u8, String> {
+        let value = symbols::evaluate_defined(&label, &self.labels)
+            .map_err(|e| format!("{} (operands of {} have to be defined before it)", e, directive))?;
+        absolute(value, 8).map_err(|e| format!("{} in {}", e, directive))
+    }
+
+    /// Resolves a count or address like `evaluate_now`, which can not be
+    /// negative.
+    fn evaluate_unsigned_now(&self, directive: &str, label: Label) -> Result<usize, String> {
+        let value = symbols::evaluate_defined(&label, &self.labels)
+            .map_err(|e| format!("{} (operands of {} have to be defined before it)", e, directive))?;
+        if value.offset < 0 {
+            return Err(format!("{} can not be negative in {}", value.offset, directive));
+        }
+        absolute(value, 8).map(usize::from).map_err(|e| format!("{} in {}", e, directive))
+    }
+
+    fn push_data(&mut self, location: &Location, value: Label) -> Result<(), String> {
+        let address = data_address(self.data_pc)?;
+        self.data.push((location.clone(), address as usize, value));
+        self.data_pc += 1;
+        Ok(())
+    }
+}
+
+fn data_address(data_pc: usize) -> Result<u8, String> {
+    u8::try_from(data_pc)
+        .map_err(|_| format!("Data does not fit in the {} bytes of memory", MEMORY_SIZE))
+}
+
+/// Reads a register name, `V0` to `VF` in any case.
+fn register(token: &str) -> Option<u8> {
+    match token.as_bytes() {
+        [b'V' | b'v', digit] => (*digit as char).to_digit(16).map(|digit| digit as u8),
+        _ => None,
+    }
+}
+
+/// Strips the quotes from a string token and resolves escapes.
+fn unquote(token: &str) -> Result<String, String> {
+    let inner = token
+        .strip_prefix('"')
+        .and_then(|s| s.strip_suffix('"'))
+        .ok_or_else(|| format!("Expected string, found {}", token))?;
+
+    lexer::unescape(inner)
+}
+
+/// Encodes one instruction as its two bytes.
+fn encode(token: Instructions, encoder: &mut Encoder, binary: &mut Vec<u8>) -> Result<(), String> {
+    match token {
+        Instructions::Jump(label) => {
+            binary.push(0x00);
+            binary.push(encoder.u8(label, object::Section::Code, binary.len())?);
+        },
+        Instructions::IfEq(label1, label2) => {
+            binary.push(0x10);
+            binary.push(u4u4_to_u8(
+                encoder.u4(&label1)?,
+                encoder.u4(&label2)?
+            ));
+        },
+        Instructions::IfNeq(label1, label2) => {
+            binary.push(0x20);
+            binary.push(u4u4_to_u8(
+                encoder.u4(&label1)?,
+                encoder.u4(&label2)?
+            ));
+        },
+        Instructions::IfLe(label1, label2) => {
+            binary.push(0x30);
+            binary.push(u4u4_to_u8(
+                encoder.u4(&label1)?,
+                encoder.u4(&label2)?
+            ));
+        },
+        Instructions::SetRr(label1, label2) => {
+            binary.push(0x40);
+            binary.push(u4u4_to_u8(
+                encoder.u4(&label1)?,
+                encoder.u4(&label2)?
+            ));
+        },
+        Instructions::SetRpc(label) => {
+            binary.push(0x41);
+            binary.push(encoder.u4(&label)?);
+        },
+        Instructions::SetRm(label1, label2) => {
+            binary.push(0x50 | encoder.u4(&label1)?);
+            binary.push(encoder.u8(label2, object::Section::Code, binary.len())?);
+        },
+        Instructions::SetRc(label1, label2) => {
+            binary.push(0x60 | encoder.u4(&label1)?);
+            binary.push(encoder.u8(label2, object::Section::Code, binary.len())?);
+        },
+        Instructions::SetPcr(label) => {
+            binary.push(0x70);
+            binary.push(encoder.u4(&label)?);
+        },
+        Instructions::SetMr(label1, label2) => {
+            binary.push(0x80 | encoder.u4(&label1)?);
+            binary.push(encoder.u8(label2, object::Section::Code, binary.len())?);
+        },
+        Instructions::Add(label1, label2) => {
+            binary.push(0x90);
+            binary.push(u4u4_to_u8(
+                encoder.u4(&label1)?,
+                encoder.u4(&label2)?
+            ));
+        },
+        Instructions::Sub(label1, label2) => {
+            binary.push(0x91);
+            binary.push(u4u4_to_u8(
+                encoder.u4(&label1)?,
+                encoder.u4(&label2)?
+            ));
+        },
+        Instructions::And(label1, label2) => {
+            binary.push(0xA0);
+            binary.push(u4u4_to_u8(
+                encoder.u4(&label1)?,
+                encoder.u4(&label2)?
+            ));
+        },
+        Instructions::Or(label1, label2) => {
+            binary.push(0xA1);
+            binary.push(u4u4_to_u8(
+                encoder.u4(&label1)?,
+                encoder.u4(&label2)?
+            ));
+        },
+        Instructions::Xor(label1, label2) => {
+            binary.push(0xA2);
+            binary.push(u4u4_to_u8(
+                encoder.u4(&label1)?,
+                encoder.u4(&label2)?
+            ));
+        },
+        Instructions::Not(label) => {
+            binary.push(0xA3);
+            binary.push(u4u4_to_u8(encoder.u4(&label)?, 0));
+        },
+    }
+
+    Ok(())
+}
+
+/// Reads one operand. An operand is a single value or an expression, which
+/// can span several tokens like `@N - 1`.
+fn get_label(code: &mut Tokens) -> Result<Label, String> {
+    let mut s = code.next()
+        .ok_or("Expected value")?;
+    while expr::continues(&s, code.peek().map(String::as_str)) {
+        let next = code.next()
+            .ok_or_else(|| format!("Unexpected end of expression {}", s))?;
+        s.push(' ');
+        s.push_str(&next);
+    }
+
+    match expr::parse(&s)? {
+        Expr::Number(n) if (0..=0xFF).contains(&n) => Ok(Label::u8(n as u8)),
+        Expr::Symbol(name) => Ok(Label::Label(name)),
+        expr => Ok(Label::Expression(Box::new(expr))),
+    }
+}
+
+impl Encoder<'_> {
+    /// Resolves an operand that is encoded into the 8-bit field at `offset`
+    /// of `section`.
+    fn u8(&mut self, label: Label, section: object::Section, offset: usize) -> Result<u8, String> {
+        let value = self.value(&label)?;
+        let target = match &value.base {
+            Base::Absolute => return fit(value.offset, 8),
+            Base::Code => Target::Code,
+            Base::Data => Target::Data,
+            Base::Extern(name) if self.relocatable => {
+                let import = self.externs.iter().position(|import| import == name).unwrap_or_default();
+                Target::Import(import as u16)
             },
-            Instructions::SetMr(label1, label2) => {
-                binary.push(0x80 | label_to_u8(label1, &labels));
-                binary.push(label_to_u8(label2, &labels));
+            Base::Extern(name) => {
+                return Err(format!("@{} is .extern, assemble with -c and link the object files", name));
             },
-            Instructions::Add(label1, label2) => {
-                binary.push(0x90);
-                binary.push(u4u4_to_u8(
-                    label_to_u8(label1, &labels), 
-                    label_to_u8(label2, &labels)
-                ));
+        };
+        let addend = i16::try_from(value.offset)
+            .map_err(|_| format!("{} does not fit in 16 bits", value.offset))?;
+        self.relocations.push(Relocation { section, offset: offset as u16, target, addend });
+        Ok(0)
+    }
+
+    /// Resolves a register operand.
+    fn u4(&mut self, label: &Label) -> Result<u8, String> {
+        match label {
+            Label::Register(register) => Ok(*register),
+            Label::NotScratch(label) => match self.u4(label)? {
+                0xF => Err("inc and dec use VF as scratch register and can not change VF".to_string()),
+                register => Ok(register),
             },
-            Instructions::Sub(label1, label2) => {
-                binary.push(0x91);
-                binary.push(u4u4_to_u8(
-                    label_to_u8(label1, &labels), 
-                    label_to_u8(label2, &labels)
-                ));
+            _ if self.strict => Err("Expected a register like V2 (plain numbers are not registers in strict mode)".to_string()),
+            label => absolute(self.value(label)?, 4),
+        }
+    }
+
+    fn value(&self, label: &Label) -> Result<Value, String> {
+        let lookup = |name: &str| {
+            self.symbols
+                .get(name)
+                .cloned()
+                .ok_or_else(|| format!("Label not found: @{}", name))
+        };
+
+        match label {
+            Label::Label(s) => {
+                lookup(s)
             },
-            Instructions::And(label1, label2) => {
-                binary.push(0xA0);
-                binary.push(u4u4_to_u8(
-                    label_to_u8(label1, &labels), 
-                    label_to_u8(label2, &labels)
-                ));
+            Label::u8(u) => {
+                Ok(Value::absolute(*u as i64))
             },
-            Instructions::Or(label1, label2) => {
-                binary.push(0xA1);
-                binary.push(u4u4_to_u8(
-                    label_to_u8(label1, &labels), 
-                    label_to_u8(label2, &labels)
-                ));
+            Label::Expression(expr) => {
+                expr.evaluate(&mut |name| lookup(name))
             },
-            Instructions::Xor(label1, label2) => {
-                binary.push(0xA2);
-                binary.push(u4u4_to_u8(
-                    label_to_u8(label1, &labels), 
-                    label_to_u8(label2, &labels)
-                ));
+            Label::Register(register) => {
+                Err(format!("V{:X} is a register, expected a value", register))
             },
-            Instructions::Not(label) => {
-                binary.push(0xA3);
-                binary.push(label_to_u8(label, &labels));
+            Label::Address(pc) => {
+                let base = if self.relocatable { Base::Code } else { Base::Absolute };
+                Ok(Value { base, offset: *pc as i64 })
+            }
+            Label::NotScratch(label) => {
+                self.value(label)
             },
         }
     }
-
-    fs::write(path.replace(".asm", ".bin"), binary).unwrap();
 }
 
-fn get_label(code: &mut Peekable<SplitWhitespace>) -> Label {
-    let s = code.next()
-        .expect("Expected value");
-    if s.starts_with("0x") {
-        Label::u8(u8::from_str_radix(
-            s
-            .replace("0x", "")
-            .as_str(), 
-            16
-        ).expect("Not hex"))
-    }
-    else if s.starts_with("0b") {
-        Label::u8(u8::from_str_radix(
-            s
-            .replace("0b", "")
-            .as_str(), 
-            2
-        ).expect("Not binary"))
-    }
-    else if s.starts_with("@") {
-        Label::Label(s.replace("@", ""))
-    }
-    else {
-        Label::u8(u8::from_str_radix(
-            s, 
-            10
-        ).expect("Not decimal"))
+/// Checks that a value is known before linking and fits in `bits` bits.
+fn absolute(value: Value, bits: u32) -> Result<u8, String> {
+    match value.base {
+        Base::Absolute => fit(value.offset, bits),
+        Base::Extern(name) => Err(format!("@{} is .extern and only known when linking", name)),
+        _ => Err("The address is only known when linking".to_string()),
     }
 }
 
-fn label_to_u8(label: Label, labels: &HashMap<String, Label>) -> u8 {
-    match label {
-        Label::Label(s) => {
-            match labels.get(&s) {
-                Some(Label::u8(u)) => {
-                    *u
-                },
-                _ => {
-                    panic!("Label not found");
-                }
-            }
-        },
-        Label::u8(u) => {
-            u
-        }
+/// Checks that a value fits in a field of `bits` bits. 8-bit fields also take
+/// negative values down to -128, stored as two's complement.
+fn fit(value: i64, bits: u32) -> Result<u8, String> {
+    let range = if bits == 8 { -0x80..=0xFF } else { 0..=(1 << bits) - 1 };
+    if !range.contains(&value) {
+        return Err(format!("{} does not fit in {} bits", value, bits));
     }
+    Ok(value as u8)
 }
 
 fn u4u4_to_u8(u41: u8, u42: u8) -> u8 {
     u41 << 4 | u42
-}
\ No newline at end of file
+}
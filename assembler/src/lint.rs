@@ -0,0 +1,185 @@
+/*
+Lints for code that assembles but probably does not do what was meant. They
+run on the instructions from the first pass, after symbols are resolved:
+
+* code after an unconditional `jump` or `setpcr` that no label or jump leads to
+* labels that are never used
+* writes to VF that the next `add` or `sub` overwrites with its flag
+* jumps past the end of the program
+* a skip as the last instruction, which skips nothing
+* constants that do not fit in the 8 bit values of the symbol table
+*/
+
+use std::collections::HashSet;
+
+use crate::{expr::Base, Encoder, Instructions, Label, Parser, Section};
+
+/// Returns the warnings for the parsed file, located like errors.
+pub(crate) fn lint(parser: &Parser, encoder: &Encoder) -> Vec<String> {
+    let mut warnings = Vec::new();
+    let code = &parser.code;
+
+    // addresses that a label or jump leads to
+    let mut reachable = code
+        .iter()
+        .filter_map(|(_, instruction)| match instruction {
+            Instructions::Jump(label) => address(label, encoder),
+            _ => None,
+        })
+        .collect::<HashSet<_>>();
+    reachable.extend(
+        parser.placed
+            .iter()
+            .filter(|(_, section, _)| *section == Section::Code)
+            .map(|(_, _, address)| *address as usize),
+    );
+    if let Some(entry) = parser.entry.as_ref().and_then(|(_, label)| address(label, encoder)) {
+        reachable.insert(entry);
+    }
+
+    for (pc, pair) in code.windows(2).enumerate() {
+        let [(_, instruction), (location, _)] = pair else { continue };
+        let conditional = pc > 0 && is_skip(&code[pc - 1].1);
+        let jumps = matches!(instruction, Instructions::Jump(_) | Instructions::SetPcr(_));
+        if jumps && !conditional && !reachable.contains(&(pc + 1)) {
+            warnings.push(location.error("Unreachable code after jump".to_string()));
+        }
+    }
+
+    let mut used = Vec::new();
+    for (_, instruction) in code {
+        operands(instruction).into_iter().for_each(|label| symbols(label, &mut used));
+    }
+    parser.data.iter().for_each(|(_, _, label)| symbols(label, &mut used));
+    parser.labels.values().for_each(|definition| symbols(&definition.value, &mut used));
+    if let Some((_, label)) = &parser.entry {
+        symbols(label, &mut used);
+    }
+    for test in &parser.tests {
+        test.operands().into_iter().for_each(|label| symbols(label, &mut used));
+    }
+    used.extend(parser.globals.iter().map(|(_, name)| name.as_str()));
+
+    for (name, ..) in &parser.placed {
+        if !used.contains(&name.as_str()) {
+            warnings.push(parser.labels[name].location.error(format!("@{} is never used", name)));
+        }
+    }
+
+    for pair in code.windows(2) {
+        let [(location, instruction), (_, next)] = pair else { continue };
+        let writes_flag = written(instruction).and_then(|label| register(label, encoder)) == Some(0xF);
+        let clobbers = match next {
+            Instructions::Add(vx, vy) | Instructions::Sub(vx, vy) => {
+                ![vx, vy].into_iter().any(|label| register(label, encoder) == Some(0xF))
+            },
+            _ => false,
+        };
+        if writes_flag && clobbers {
+            warnings.push(location.error("VF is overwritten by the flag of the next instruction".to_string()));
+        }
+    }
+
+    for (location, instruction) in code {
+        let Instructions::Jump(label) = instruction else { continue };
+        match address(label, encoder) {
+            Some(target) if target > code.len() => warnings.push(location.error(format!(
+                "Jump to {} is past the end of the program ({} instructions)",
+                target, code.len()
+            ))),
+            _ => {},
+        }
+    }
+
+    if let Some((location, instruction)) = code.last() {
+        if is_skip(instruction) {
+            warnings.push(location.error("Skip as the last instruction, there is nothing to skip".to_string()));
+        }
+    }
+
+    let mut constants = encoder.symbols
+        .iter()
+        .filter(|(_, value)| value.base == Base::Absolute && !(-0x80..=0xFF).contains(&value.offset))
+        .filter_map(|(name, value)| Some((parser.labels.get(name)?, name, value.offset)))
+        .collect::<Vec<_>>();
+    constants.sort_by_key(|(definition, name, _)| (definition.location.line, *name));
+    for (definition, name, value) in constants {
+        warnings.push(definition.location.error(format!(
+            "@{} = {} does not fit in 8 bits and is left out of the symbol table",
+            name, value
+        )));
+    }
+
+    warnings
+}
+
+fn is_skip(instruction: &Instructions) -> bool {
+    matches!(instruction, Instructions::IfEq(..) | Instructions::IfNeq(..) | Instructions::IfLe(..))
+}
+
+/// The code address an operand points to, if it is known.
+fn address(label: &Label, encoder: &Encoder) -> Option<usize> {
+    match encoder.value(label).ok()? {
+        value if value.base == Base::Absolute || value.base == Base::Code => usize::try_from(value.offset).ok(),
+        _ => None,
+    }
+}
+
+/// The register an operand names, if it is known.
+fn register(label: &Label, encoder: &Encoder) -> Option<u8> {
+    match label {
+        Label::Register(register) => Some(*register),
+        Label::NotScratch(label) => register(label, encoder),
+        label => match encoder.value(label).ok()? {
+            value if value.base == Base::Absolute => u8::try_from(value.offset).ok().filter(|r| *r < 0x10),
+            _ => None,
+        },
+    }
+}
+
+/// The register operand an instruction writes to.
+fn written(instruction: &Instructions) -> Option<&Label> {
+    match instruction {
+        Instructions::SetRr(vx, _)
+        | Instructions::SetRm(vx, _)
+        | Instructions::SetRc(vx, _)
+        | Instructions::Add(vx, _)
+        | Instructions::Sub(vx, _)
+        | Instructions::And(vx, _)
+        | Instructions::Or(vx, _)
+        | Instructions::Xor(vx, _)
+        | Instructions::Not(vx) => Some(vx),
+        _ => None,
+    }
+}
+
+fn operands(instruction: &Instructions) -> Vec<&Label> {
+    match instruction {
+        Instructions::Jump(a)
+        | Instructions::SetRpc(a)
+        | Instructions::SetPcr(a)
+        | Instructions::Not(a) => vec![a],
+        Instructions::IfEq(a, b)
+        | Instructions::IfNeq(a, b)
+        | Instructions::IfLe(a, b)
+        | Instructions::SetRr(a, b)
+        | Instructions::SetRm(a, b)
+        | Instructions::SetRc(a, b)
+        | Instructions::SetMr(a, b)
+        | Instructions::Add(a, b)
+        | Instructions::Sub(a, b)
+        | Instructions::And(a, b)
+        | Instructions::Or(a, b)
+        | Instructions::Xor(a, b) => vec![a, b],
+    }
+}
+
+/// Adds the names of the symbols an operand uses to `names`.
+fn symbols<'a>(label: &'a Label, names: &mut Vec<&'a str>) {
+    match label {
+        Label::Label(name) => names.push(name),
+        Label::Expression(expr) => expr.symbols(names),
+        Label::NotScratch(label) => symbols(label, names),
+        _ => {},
+    }
+}
@@ -0,0 +1,181 @@
+/*
+Macros are defined with
+
+.macro name param1 param2
+    ...
+.endm
+
+and used like an instruction, `name arg1 arg2`. In the body `\param` is
+replaced with the argument, except in string and character literals where
+`\n` is an escape. Labels starting with `@.` are local, they get a suffix
+that is unique for every expansion so a macro can be used more than once.
+Macros have to be defined before they are used and can use other macros,
+but not themselves. A definition inside an inactive `.if` branch is skipped
+like any other line.
+*/
+
+use std::collections::HashMap;
+
+use crate::lexer::{Diagnostic, Line, Location};
+
+struct Macro {
+    params: Vec<String>,
+    body: Vec<Line>,
+    location: Location,
+}
+
+/// The macros defined so far, fed one line at a time by the parser.
+#[derive(Default)]
+pub(crate) struct Macros {
+    macros: HashMap<String, Macro>,
+    /// The macro whose body is being collected.
+    defining: Option<(String, Macro)>,
+    expansions: usize,
+}
+
+impl Macros {
+    /// A `.macro` body is being collected, so lines go to `body`.
+    pub fn defining(&self) -> bool {
+        self.defining.is_some()
+    }
+
+    /// Starts the definition of a macro at its `.macro` line.
+    pub fn define(&mut self, line: &Line) -> Result<(), Diagnostic> {
+        let (name, params) = match &line.tokens[1..] {
+            [name, params @ ..] => (name.clone(), params.to_vec()),
+            [] => return Err(line.location.diagnostic(".macro expects a name".to_string())),
+        };
+        if let Some(param) = params.iter().find(|param| !is_identifier(param)) {
+            return Err(line.location.diagnostic(format!("Invalid macro parameter: {}", param)));
+        }
+
+        self.defining = Some((name, Macro { params, body: Vec::new(), location: line.location.clone() }));
+        Ok(())
+    }
+
+    /// Adds a line to the body of the macro being defined, or ends the
+    /// definition at `.endm`.
+    pub fn body(&mut self, line: Line) -> Result<(), Diagnostic> {
+        match (line.tokens[0].as_str(), self.defining.take()) {
+            (".endm", Some((name, definition))) => {
+                if let Some(previous) = self.macros.get(&name) {
+                    return Err(definition.location.diagnostic(format!(
+                        "Macro {} is already defined at {}", name, previous.location.describe()
+                    )));
+                }
+                self.macros.insert(name, definition);
+            },
+            (".macro", Some((name, _))) => {
+                return Err(line.location.diagnostic(format!("Macros can not be defined inside macro {}", name)));
+            },
+            (_, Some((name, mut definition))) => {
+                definition.body.push(line);
+                self.defining = Some((name, definition));
+            },
+            (_, None) => {
+                return Err(line.location.diagnostic(".endm without .macro".to_string()));
+            },
+        }
+        Ok(())
+    }
+
+    /// Errors if a `.macro` is still open at the end of the source.
+    pub fn finish(&self) -> Result<(), Diagnostic> {
+        match &self.defining {
+            Some((name, definition)) => Err(definition.location.diagnostic(format!(".macro {} has no .endm", name))),
+            None => Ok(()),
+        }
+    }
+
+    /// The lines of the macro body if `line` uses a macro. Macros used in
+    /// the body are expanded when the parser reaches them.
+    pub fn expand(&mut self, line: &Line) -> Result<Option<Vec<Line>>, Diagnostic> {
+        let name = &line.tokens[0];
+        let Some(definition) = self.macros.get(name) else {
+            return Ok(None);
+        };
+
+        if line.location.expansions.iter().any(|(expanding, _)| expanding == name) {
+            return Err(line.location.diagnostic(format!("Macro {} uses itself", name)));
+        }
+
+        let args = &line.tokens[1..];
+        if args.len() != definition.params.len() {
+            return Err(line.location.diagnostic(format!(
+                "Macro {} expects {} arguments, found {}",
+                name, definition.params.len(), args.len()
+            )));
+        }
+
+        self.expansions += 1;
+        let id = self.expansions;
+
+        let mut call = line.location.clone();
+        call.expansions.clear();
+        let lines = definition.body
+            .iter()
+            .map(|body_line| {
+                let tokens = body_line.tokens
+                    .iter()
+                    .map(|token| substitute(token, &definition.params, args, id))
+                    .collect();
+
+                let mut location = body_line.location.clone();
+                location.expansions.push((name.clone(), call.clone()));
+                location.expansions.extend(line.location.expansions.iter().cloned());
+                Line { tokens, location }
+            })
+            .collect();
+
+        Ok(Some(lines))
+    }
+}
+
+/// Replaces `\param` with its argument and makes `@.local` labels unique to
+/// the expansion. String and character literals are copied as they are, so
+/// their escapes are not taken for parameters.
+fn substitute(token: &str, params: &[String], args: &[String], id: usize) -> String {
+    let mut result = String::new();
+    let mut quote = None;
+    let mut escaped = false;
+    let mut chars = token.char_indices().peekable();
+
+    while let Some((i, c)) = chars.next() {
+        if let Some(open) = quote {
+            result.push(c);
+            if c == open && !escaped {
+                quote = None;
+            }
+            escaped = c == '\\' && !escaped;
+            continue;
+        }
+        match c {
+            '"' | '\'' => {
+                quote = Some(c);
+                result.push(c);
+            },
+            '\\' => {
+                let after = &token[i + 1..];
+                let length = after
+                    .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                    .unwrap_or(after.len());
+
+                match params.iter().position(|param| *param == after[..length]) {
+                    Some(param) => result.push_str(&args[param]),
+                    None => result.push_str(&token[i..i + 1 + length]),
+                }
+                while chars.next_if(|(j, _)| *j <= i + length).is_some() {}
+            },
+            c => result.push(c),
+        }
+    }
+
+    if result.starts_with("@.") {
+        result.push_str(&format!(".{}", id));
+    }
+    result
+}
+
+fn is_identifier(s: &str) -> bool {
+    !s.is_empty() && s.chars().all(|c| c.is_alphanumeric() || c == '_')
+}
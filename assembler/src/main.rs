@@ -1,5 +1,178 @@
-use std::env::args;
+use std::{env::args, fs, io::{self, Write}, path::Path, process::ExitCode};
 
-fn main() {
-    assembler::run(&args().nth(1).expect("No file path provided"));
-}
\ No newline at end of file
+use assembler::{output::{self, Format}, Diagnostic, Options as AssemblerOptions};
+
+const USAGE: &str = "\
+Usage: assembler [options] <file.asm>...
+
+Options:
+    -o <file>          Write output to <file> (only with a single input)
+    -I <dir>           Search <dir> for .include files (can be repeated)
+    -D <name>[=value]  Define @name as value (default: 1) before assembling
+    --lint             Print warnings for likely mistakes instead of writing output
+    --strict           Only accept V0-VF and aliases as register operands
+    -c                 Write a relocatable object file (.o) for the linker
+    --format <format>  Output format: bin, hex, ihex, srec or exe (default: bin)
+    -g                 Include source lines and labels for the emulator (exe format only)
+    --stdout           Write output to stdout instead of a file
+    -h, --help         Print this help";
+
+struct Options {
+    assembler: AssemblerOptions,
+    output: Option<String>,
+    format: Format,
+    object: bool,
+    debug: bool,
+    lint: bool,
+    stdout: bool,
+    inputs: Vec<String>,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Option<Options>, String> {
+    let mut options = Options {
+        assembler: AssemblerOptions::default(),
+        output: None,
+        format: Format::Bin,
+        object: false,
+        debug: false,
+        lint: false,
+        stdout: false,
+        inputs: Vec::new(),
+    };
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" => {
+                options.output = Some(args.next().ok_or("-o expects a file")?);
+            },
+            "-I" => {
+                options.assembler.include_paths.push(args.next().ok_or("-I expects a directory")?.into());
+            },
+            "-D" => {
+                let define = args.next().ok_or("-D expects a name")?;
+                options.assembler.defines.push(parse_define(&define)?);
+            },
+            _ if arg.starts_with("-D") => {
+                options.assembler.defines.push(parse_define(&arg[2..])?);
+            },
+            "--format" => {
+                options.format = args.next().ok_or("--format expects a format")?.parse()?;
+            },
+            "--lint" => {
+                options.lint = true;
+            },
+            "--strict" => {
+                options.assembler.strict = true;
+            },
+            "-c" => {
+                options.object = true;
+            },
+            "-g" => {
+                options.debug = true;
+            },
+            "--stdout" => {
+                options.stdout = true;
+            },
+            "-h" | "--help" => {
+                return Ok(None);
+            },
+            _ if arg.starts_with('-') => {
+                return Err(format!("Unknown option: {}", arg));
+            },
+            _ => {
+                options.inputs.push(arg);
+            }
+        }
+    }
+
+    if options.inputs.is_empty() {
+        return Err("No file path provided".to_string());
+    }
+    if options.output.is_some() && options.inputs.len() > 1 {
+        return Err("-o can only be used with a single input file".to_string());
+    }
+    if options.output.is_some() && options.stdout {
+        return Err("-o and --stdout can not be used together".to_string());
+    }
+    if options.debug && (options.object || options.format != Format::Exe) {
+        return Err("-g needs --format exe".to_string());
+    }
+
+    Ok(Some(options))
+}
+
+/// Splits `NAME=value` from `-D`, the value defaults to 1.
+fn parse_define(define: &str) -> Result<(String, String), String> {
+    let (name, value) = define.split_once('=').unwrap_or((define, "1"));
+    let name = name.trim_start_matches('@');
+    if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '.') {
+        return Err(format!("Invalid name for -D: {}", name));
+    }
+    Ok((name.to_string(), value.to_string()))
+}
+
+fn main() -> ExitCode {
+    let options = match parse_args(args().skip(1)) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        },
+        Err(e) => {
+            eprintln!("error: {}", e);
+            eprintln!("{}", USAGE);
+            return ExitCode::from(2);
+        }
+    };
+
+    let mut failed = false;
+
+    for input in &options.inputs {
+        if let Err(diagnostics) = assemble(input, &options) {
+            diagnostics.iter().for_each(|diagnostic| eprintln!("error: {}: {}", input, diagnostic));
+            failed = true;
+        }
+    }
+
+    if failed { ExitCode::FAILURE } else { ExitCode::SUCCESS }
+}
+
+fn assemble(input: &str, options: &Options) -> Result<(), Vec<Diagnostic>> {
+    if options.lint {
+        assembler::lint_file(input, &options.assembler)?
+            .iter()
+            .for_each(|warning| eprintln!("warning: {}: {}", input, warning));
+        return Ok(());
+    }
+
+    let (encoded, extension) = if options.object {
+        let object = assembler::assemble_object(input, &options.assembler)?;
+        (object.to_bytes().map_err(|e| vec![e.into()])?, "o")
+    }
+    else {
+        let assembled = assembler::assemble_file(input, &options.assembler)?;
+        let mut executable = assembled.program;
+        if options.debug {
+            executable.debug = Some(assembled.debug);
+        }
+        if !executable.data.is_empty() && options.format != Format::Exe {
+            let e = format!("the program has a .data section, which only the exe format can hold (not {})", options.format);
+            return Err(vec![e.into()]);
+        }
+        let encoded = output::encode(&executable, options.format).map_err(|e| vec![e.into()])?;
+        (encoded, options.format.extension())
+    };
+
+    if options.stdout {
+        return io::stdout()
+            .write_all(&encoded)
+            .map_err(|e| vec![e.to_string().into()]);
+    }
+
+    let path = match &options.output {
+        Some(path) => path.into(),
+        None => Path::new(input).with_extension(extension),
+    };
+
+    fs::write(&path, encoded).map_err(|e| vec![format!("{}: {}", path.display(), e).into()])
+}
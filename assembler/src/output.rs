@@ -0,0 +1,138 @@
+use std::{fmt::Display, str::FromStr};
+
+use exe::Executable;
+
+/// Output formats the assembler can write a binary as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Raw bytes, two per instruction.
+    Bin,
+    /// Whitespace separated hex words, one instruction per line (like `fib.txt`).
+    Hex,
+    /// Intel HEX records.
+    Ihex,
+    /// Motorola S-records.
+    Srec,
+    /// Executable container with entry point, data and symbols.
+    Exe,
+}
+
+impl Format {
+    /// File extension used when the output path is derived from the input path.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Format::Bin => "bin",
+            Format::Hex => "txt",
+            Format::Ihex => "ihex",
+            Format::Srec => "srec",
+            Format::Exe => "exe",
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bin" => Ok(Format::Bin),
+            "hex" => Ok(Format::Hex),
+            "ihex" => Ok(Format::Ihex),
+            "srec" => Ok(Format::Srec),
+            "exe" => Ok(Format::Exe),
+            _ => Err(format!("Unknown format: {} (expected bin, hex, ihex, srec or exe)", s)),
+        }
+    }
+}
+
+impl Display for Format {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Format::Bin => write!(f, "bin"),
+            Format::Hex => write!(f, "hex"),
+            Format::Ihex => write!(f, "ihex"),
+            Format::Srec => write!(f, "srec"),
+            Format::Exe => write!(f, "exe"),
+        }
+    }
+}
+
+/// Encodes an assembled program in the given format. Only `Exe` keeps the
+/// entry point and symbols, the other formats hold just the code.
+pub fn encode(executable: &Executable, format: Format) -> Result<Vec<u8>, String> {
+    let binary = &executable.code;
+    Ok(match format {
+        Format::Bin => binary.to_vec(),
+        Format::Hex => to_hex(binary).into_bytes(),
+        Format::Ihex => to_ihex(binary).into_bytes(),
+        Format::Srec => to_srec(binary).into_bytes(),
+        Format::Exe => executable.to_bytes()?,
+    })
+}
+
+fn to_hex(binary: &[u8]) -> String {
+    let mut string = String::new();
+    binary.chunks(2).for_each(|word| {
+        string.push_str(&to_hex_digits(word));
+        string.push('\n');
+    });
+    string
+}
+
+/// Bytes per Intel HEX data record.
+const IHEX_RECORD_SIZE: usize = 16;
+
+fn to_ihex(binary: &[u8]) -> String {
+    let mut string = String::new();
+    binary
+        .chunks(IHEX_RECORD_SIZE)
+        .enumerate()
+        .for_each(|(i, data)| {
+            string.push_str(&ihex_record((i * IHEX_RECORD_SIZE) as u16, 0x00, data));
+        });
+    string.push_str(&ihex_record(0, 0x01, &[]));
+    string
+}
+
+fn ihex_record(address: u16, record_type: u8, data: &[u8]) -> String {
+    let mut bytes = vec![data.len() as u8, (address >> 8) as u8, address as u8, record_type];
+    bytes.extend_from_slice(data);
+    bytes.push(sum(&bytes).wrapping_neg());
+
+    format!(":{}\n", to_hex_digits(&bytes))
+}
+
+/// Bytes per S-record data record.
+const SREC_RECORD_SIZE: usize = 16;
+
+fn to_srec(binary: &[u8]) -> String {
+    let mut string = srec_record('0', 0, b"HDR");
+    let records = binary.chunks(SREC_RECORD_SIZE).len();
+    binary
+        .chunks(SREC_RECORD_SIZE)
+        .enumerate()
+        .for_each(|(i, data)| {
+            string.push_str(&srec_record('1', (i * SREC_RECORD_SIZE) as u16, data));
+        });
+    string.push_str(&srec_record('5', records as u16, &[]));
+    string.push_str(&srec_record('9', 0, &[]));
+    string
+}
+
+fn srec_record(record_type: char, address: u16, data: &[u8]) -> String {
+    let mut bytes = vec![(data.len() + 3) as u8, (address >> 8) as u8, address as u8];
+    bytes.extend_from_slice(data);
+    bytes.push(!sum(&bytes));
+
+    format!("S{}{}\n", record_type, to_hex_digits(&bytes))
+}
+
+fn sum(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+}
+
+fn to_hex_digits(bytes: &[u8]) -> String {
+    let mut string = String::new();
+    bytes.iter().for_each(|byte| string.push_str(&format!("{:02X}", byte)));
+    string
+}
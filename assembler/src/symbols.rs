@@ -0,0 +1,139 @@
+/*
+Symbol resolution. Labels get their address during the first pass, but
+constants (`@NAME = value`) can refer to labels and other constants defined
+anywhere in the file, so they are resolved here once every definition is
+known.
+
+Labels are absolute when assembling a program. In object files they are
+relative to their section and `.extern` symbols are defined as themselves,
+which the linker fills in.
+*/
+
+use std::collections::HashMap;
+
+use crate::{expr::{Base, Value}, lexer::{Diagnostic, Location}, Label};
+
+/// A label or constant and where it was defined.
+pub(crate) struct Definition {
+    pub value: Label,
+    /// What `value` is relative to, `Base::Absolute` for constants so they
+    /// keep the base of their expression.
+    pub base: Base,
+    pub location: Location,
+    /// Position among all definitions, so errors are reported in source order.
+    pub order: usize,
+}
+
+enum Error {
+    /// An error in the definition currently being resolved.
+    Unlocated(String),
+    /// An error that already points at the definition it happened in.
+    Located(Diagnostic),
+}
+
+/// Resolves the value of every symbol. Errors point at the definition they
+/// happen in, and definitions that depend on themselves are reported as
+/// circular.
+pub(crate) fn resolve_all(definitions: &HashMap<String, Definition>) -> Result<HashMap<String, Value>, Diagnostic> {
+    let mut names = definitions.keys().collect::<Vec<_>>();
+    names.sort_by_key(|name| definitions[*name].order);
+
+    let mut resolved = HashMap::new();
+    for name in names {
+        match resolve(name, definitions, &mut resolved, &mut Vec::new()) {
+            Ok(_) => {},
+            Err(Error::Located(e)) => return Err(e),
+            Err(Error::Unlocated(e)) => return Err(definitions[name].location.diagnostic(e)),
+        }
+    }
+
+    Ok(resolved)
+}
+
+/// Evaluates an operand with the symbols that are defined so far, for
+/// directives like `.org` that need the value during the first pass.
+pub(crate) fn evaluate_defined(label: &Label, definitions: &HashMap<String, Definition>) -> Result<Value, String> {
+    match value(label, definitions, &mut HashMap::new(), &mut Vec::new()) {
+        Ok(value) => Ok(value),
+        Err(Error::Located(e)) => Err(e.to_string()),
+        Err(Error::Unlocated(e)) => Err(e),
+    }
+}
+
+fn resolve(
+    name: &str,
+    definitions: &HashMap<String, Definition>,
+    resolved: &mut HashMap<String, Value>,
+    stack: &mut Vec<String>,
+) -> Result<Value, Error> {
+    if let Some(value) = resolved.get(name) {
+        return Ok(value.clone());
+    }
+
+    if let Some(start) = stack.iter().position(|resolving| resolving == name) {
+        let cycle = stack[start..]
+            .iter()
+            .map(String::as_str)
+            .chain([name])
+            .map(|name| format!("@{}", name))
+            .collect::<Vec<_>>()
+            .join(" -> ");
+        return Err(Error::Unlocated(format!("Circular definition: {}", cycle)));
+    }
+
+    let definition = definitions
+        .get(name)
+        .ok_or_else(|| Error::Unlocated(format!("Label not found: @{}", name)))?;
+
+    stack.push(name.to_string());
+    let value = value(&definition.value, definitions, resolved, stack);
+    stack.pop();
+
+    let value = match value {
+        Ok(value) => value,
+        Err(Error::Unlocated(e)) => return Err(Error::Located(definition.location.diagnostic(e))),
+        Err(e) => return Err(e),
+    };
+    let value = match &definition.base {
+        Base::Absolute => value,
+        base => Value { base: base.clone(), offset: value.offset },
+    };
+
+    resolved.insert(name.to_string(), value.clone());
+    Ok(value)
+}
+
+fn value(
+    label: &Label,
+    definitions: &HashMap<String, Definition>,
+    resolved: &mut HashMap<String, Value>,
+    stack: &mut Vec<String>,
+) -> Result<Value, Error> {
+    match label {
+        Label::u8(u) => Ok(Value::absolute(*u as i64)),
+        Label::Label(name) => resolve(name, definitions, resolved, stack),
+        Label::Register(register) => Err(Error::Unlocated(format!(
+            "V{:X} is a register, use .alias to name registers", register
+        ))),
+        Label::Address(pc) => Ok(Value::absolute(*pc as i64)),
+        Label::NotScratch(label) => value(label, definitions, resolved, stack),
+        Label::Expression(expr) => {
+            // keeps the error of a nested definition so it is not located twice
+            let mut nested = None;
+            let value = expr.evaluate(&mut |name| {
+                resolve(name, definitions, resolved, stack).map_err(|e| match e {
+                    Error::Unlocated(e) => e,
+                    Error::Located(e) => {
+                        nested = Some(e);
+                        String::new()
+                    }
+                })
+            });
+            match (value, nested) {
+                (Ok(value), _) => Ok(value),
+                (Err(_), Some(e)) => Err(Error::Located(e)),
+                (Err(e), None) => Err(Error::Unlocated(e)),
+            }
+        }
+    }
+}
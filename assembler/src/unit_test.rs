@@ -0,0 +1,260 @@
+/*
+Unit tests for assembly routines, written next to the code:
+
+.test "countdown prints 3 2 1"
+    .set V1 3
+    .mem @buffer 0 0
+    .call @countdown VE
+    .cycles 500
+    .expect V1 0
+    .expect mem @buffer 1 2
+    .expect output 3 2 1
+.endtest
+
+`.set` and `.mem` set registers and memory before the test runs. `.call` starts
+at a label instead of the entry point, and the optional register is set to the
+end of the program so a routine that returns with `setpcr VE` halts. The test
+runs until the program halts or for `.cycles` instructions (1000 by default)
+and then checks the `.expect`ed registers, memory and printed values.
+
+Operands are resolved like the operands of instructions. Tests are checked
+when assembling but do not change the output, `asmtest` runs them.
+*/
+
+use crate::{absolute, lexer::{Diagnostic, Location}, unquote, Encoder, Label, Parser, Tokens};
+
+const DEFAULT_CYCLES: usize = 1000;
+
+/// A test with its operands resolved.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Test {
+    pub name: String,
+    /// Where the `.test` block is, like `line 12`.
+    pub location: String,
+    /// PC to start at, the entry point of the program if `None`.
+    pub start: Option<u8>,
+    /// Register that is set to the end of the program before running, so
+    /// returning through it halts.
+    pub return_register: Option<u8>,
+    /// Number of instructions to run before giving up.
+    pub cycles: usize,
+    /// Registers to set before running as (register, value).
+    pub registers: Vec<(u8, u8)>,
+    /// Memory to set before running as (address, value).
+    pub memory: Vec<(u8, u8)>,
+    pub expect_registers: Vec<(u8, u8)>,
+    pub expect_memory: Vec<(u8, u8)>,
+    /// Values the program has to print, in order.
+    pub expect_output: Option<Vec<u8>>,
+}
+
+/// A `.test` block from the first pass.
+pub(crate) struct TestBlock {
+    name: String,
+    location: Location,
+    call: Option<(Location, Label, Option<Label>)>,
+    cycles: Option<(Location, Label)>,
+    setup: Vec<(Location, State)>,
+    expect: Vec<(Location, State)>,
+    /// `.endtest` has been seen.
+    closed: bool,
+}
+
+/// Registers, memory or output given in a test.
+enum State {
+    Register(Label, Label),
+    Memory(Label, Vec<Label>),
+    Output(Vec<Label>),
+}
+
+impl Parser {
+    /// Handles `.test` and the lines of a test block. Returns `true` if the
+    /// line was part of a test.
+    pub(crate) fn test_line(&mut self, location: &Location, tokens: &[String]) -> Result<bool, String> {
+        let in_test = self.tests.last().is_some_and(|test| !test.closed);
+        let mut input = Vec::from(tokens).into_iter().peekable();
+        let directive = input.next().unwrap_or_default();
+
+        if !in_test {
+            if directive != ".test" {
+                return Ok(false);
+            }
+            let name = unquote(&input.next().ok_or(".test expects a quoted name")?)?;
+            self.tests.push(TestBlock {
+                name,
+                location: location.clone(),
+                call: None,
+                cycles: None,
+                setup: Vec::new(),
+                expect: Vec::new(),
+                closed: false,
+            });
+            return self.end_of_line(&directive, input);
+        }
+
+        match directive.as_str() {
+            ".set" => {
+                let state = State::Register(self.operand(&mut input)?, self.operand(&mut input)?);
+                self.current_test().setup.push((location.clone(), state));
+            },
+            ".mem" => {
+                let state = State::Memory(self.operand(&mut input)?, self.operands(&mut input)?);
+                self.current_test().setup.push((location.clone(), state));
+            },
+            ".call" => {
+                let start = self.operand(&mut input)?;
+                let register = if input.peek().is_some() { Some(self.operand(&mut input)?) } else { None };
+                self.current_test().call = Some((location.clone(), start, register));
+            },
+            ".cycles" => {
+                let cycles = self.operand(&mut input)?;
+                self.current_test().cycles = Some((location.clone(), cycles));
+            },
+            ".expect" => {
+                let state = match input.peek().map(String::as_str) {
+                    Some("mem") => {
+                        input.next();
+                        State::Memory(self.operand(&mut input)?, self.operands(&mut input)?)
+                    },
+                    Some("output") => {
+                        input.next();
+                        State::Output(self.operands(&mut input)?)
+                    },
+                    _ => State::Register(self.operand(&mut input)?, self.operand(&mut input)?),
+                };
+                self.current_test().expect.push((location.clone(), state));
+            },
+            ".endtest" => {
+                self.current_test().closed = true;
+            },
+            ".test" => {
+                return Err("Tests can not be nested, expected .endtest".to_string());
+            },
+            _ => {
+                return Err(format!("{} is not allowed in a .test block", directive));
+            },
+        }
+
+        self.end_of_line(&directive, input)
+    }
+
+    fn current_test(&mut self) -> &mut TestBlock {
+        self.tests.last_mut().expect("a test block is open")
+    }
+
+    /// Reads the rest of the line as operands.
+    fn operands(&self, input: &mut Tokens) -> Result<Vec<Label>, String> {
+        let mut operands = Vec::new();
+        while input.peek().is_some() {
+            operands.push(self.operand(input)?);
+        }
+        Ok(operands)
+    }
+
+    fn end_of_line(&self, directive: &str, mut input: Tokens) -> Result<bool, String> {
+        match input.next() {
+            Some(token) => Err(format!("Unexpected {} after {}", token, directive)),
+            None => Ok(true),
+        }
+    }
+}
+
+impl TestBlock {
+    /// Where the test is, for errors about a test that is never closed.
+    pub(crate) fn unclosed(&self) -> Option<&Location> {
+        (!self.closed).then_some(&self.location)
+    }
+
+    /// Every operand in the test, for the lints.
+    pub(crate) fn operands(&self) -> Vec<&Label> {
+        let mut operands = Vec::new();
+        if let Some((_, start, register)) = &self.call {
+            operands.push(start);
+            operands.extend(register);
+        }
+        operands.extend(self.cycles.as_ref().map(|(_, cycles)| cycles));
+        for (_, state) in self.setup.iter().chain(&self.expect) {
+            match state {
+                State::Register(register, value) => operands.extend([register, value]),
+                State::Memory(address, values) => {
+                    operands.push(address);
+                    operands.extend(values);
+                },
+                State::Output(values) => operands.extend(values),
+            }
+        }
+        operands
+    }
+
+    /// Resolves the operands of the test.
+    pub(crate) fn resolve(&self, encoder: &mut Encoder) -> Result<Test, Diagnostic> {
+        let mut test = Test {
+            name: self.name.clone(),
+            location: self.location.describe(),
+            cycles: DEFAULT_CYCLES,
+            ..Default::default()
+        };
+
+        if let Some((location, start, register)) = &self.call {
+            let located = |e: String| location.diagnostic(e);
+            test.start = Some(value(start, encoder).map_err(located)?);
+            test.return_register = register
+                .as_ref()
+                .map(|register| encoder.u4(register).map_err(located))
+                .transpose()?;
+        }
+        if let Some((location, cycles)) = &self.cycles {
+            let cycles = encoder.value(cycles).map_err(|e| location.diagnostic(e))?;
+            test.cycles = usize::try_from(cycles.offset)
+                .map_err(|_| location.diagnostic(format!("{} is not a number of cycles", cycles.offset)))?;
+        }
+
+        for (location, state) in &self.setup {
+            resolve_state(state, encoder, &mut test.registers, &mut test.memory, &mut None)
+                .map_err(|e| location.diagnostic(e))?;
+        }
+        for (location, state) in &self.expect {
+            resolve_state(
+                state,
+                encoder,
+                &mut test.expect_registers,
+                &mut test.expect_memory,
+                &mut test.expect_output,
+            )
+            .map_err(|e| location.diagnostic(e))?;
+        }
+
+        Ok(test)
+    }
+}
+
+fn resolve_state(
+    state: &State,
+    encoder: &mut Encoder,
+    registers: &mut Vec<(u8, u8)>,
+    memory: &mut Vec<(u8, u8)>,
+    output: &mut Option<Vec<u8>>,
+) -> Result<(), String> {
+    match state {
+        State::Register(register, value_label) => {
+            registers.push((encoder.u4(register)?, value(value_label, encoder)?));
+        },
+        State::Memory(address, values) => {
+            let address = value(address, encoder)?;
+            for (offset, value_label) in values.iter().enumerate() {
+                let address = u8::try_from(address as usize + offset)
+                    .map_err(|_| "Memory past address 0xFF".to_string())?;
+                memory.push((address, value(value_label, encoder)?));
+            }
+        },
+        State::Output(values) => {
+            let values = values.iter().map(|label| value(label, encoder)).collect::<Result<Vec<_>, _>>()?;
+            output.get_or_insert_with(Vec::new).extend(values);
+        },
+    }
+    Ok(())
+}
+
+fn value(label: &Label, encoder: &Encoder) -> Result<u8, String> {
+    absolute(encoder.value(label)?, 8)
+}
@@ -0,0 +1,50 @@
+/*
+Tests for `;`, `//` and `/* */` comments, and for comment markers inside
+strings.
+*/
+
+mod common;
+
+use assembler::assemble;
+use common::{code, data, errors};
+
+#[test]
+fn line_comments() {
+    assert_eq!(code("setrc V1 3 ; three\n; a whole line\nsetrc V2 4 // four\n// another\n"), [0x61, 3, 0x62, 4]);
+    assert_eq!(code("setrc V1 3;glued\nsetrc V2 4//glued\n"), [0x61, 3, 0x62, 4]);
+}
+
+#[test]
+fn block_comments_glued_to_tokens() {
+    assert_eq!(code("setrc V1/* count */3\n"), [0x61, 3]);
+    assert_eq!(code("/* before */setrc V1 3/* after */\n"), [0x61, 3]);
+    assert_eq!(code("setrc V1 1/**/+/**/2\n"), [0x61, 3]);
+}
+
+#[test]
+fn block_comments_span_lines_and_nest() {
+    let source = "setrc V1 1 /* one\nsetrc V2 2\n/* nested */ setrc V3 3\n*/ setrc V4 4\n";
+    assert_eq!(code(source), [0x61, 1, 0x64, 4]);
+    assert_eq!(code("/* a /* b /* c */ */ */setrc V1 1\n"), [0x61, 1]);
+}
+
+#[test]
+fn comment_markers_in_strings() {
+    assert_eq!(data(".data\n.string \"; // /* */\"\n"), b"; // /* */\0");
+    assert_eq!(code("setrc V1 ';' ; comment\n"), [0x61, b';']);
+}
+
+#[test]
+fn unterminated_block_comment() {
+    let diagnostics = assemble("setrc V1 1\n/* open\n/* nested */\nsetrc V2 2\n", &Default::default()).unwrap_err();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].message, "Unterminated /* comment");
+    // reported where the comment starts
+    assert_eq!(diagnostics[0].line, Some(2));
+}
+
+#[test]
+fn stray_end_of_comment() {
+    assert_eq!(errors("setrc V1 1 */\n"), ["*/ without /*"]);
+    assert_eq!(errors("/* closed */ */\n"), ["*/ without /*"]);
+}
@@ -0,0 +1,67 @@
+/*
+Helpers shared by the assembler tests. Not every test file uses all of them.
+*/
+
+#![allow(dead_code)]
+
+use std::{env, fs, path::PathBuf, process};
+
+use assembler::{assemble, lint_file, Options};
+use exe::Executable;
+
+/// The program assembled from `source`, panicking with the diagnostics if it
+/// fails.
+pub fn program_with(source: &str, options: &Options) -> Executable {
+    match assemble(source, options) {
+        Ok(assembled) => assembled.program,
+        Err(diagnostics) => panic!("{:?}", diagnostics),
+    }
+}
+
+pub fn program(source: &str) -> Executable {
+    program_with(source, &Options::default())
+}
+
+pub fn code(source: &str) -> Vec<u8> {
+    program(source).code
+}
+
+pub fn data(source: &str) -> Vec<u8> {
+    program(source).data
+}
+
+/// The messages of the diagnostics for `source`, which has to fail.
+pub fn errors_with(source: &str, options: &Options) -> Vec<String> {
+    match assemble(source, options) {
+        Ok(_) => panic!("{:?} assembled", source),
+        Err(diagnostics) => diagnostics.into_iter().map(|diagnostic| diagnostic.message).collect(),
+    }
+}
+
+pub fn errors(source: &str) -> Vec<String> {
+    errors_with(source, &Options::default())
+}
+
+/// The message of the first diagnostic for `source`.
+pub fn error(source: &str) -> String {
+    errors(source).remove(0)
+}
+
+/// A directory of its own for a test, with `files` as (name, content) in it.
+pub fn directory(test: &str, files: &[(&str, &str)]) -> PathBuf {
+    let directory = env::temp_dir().join(format!("assembler-{}-{}", test, process::id()));
+    let _ = fs::remove_dir_all(&directory);
+    fs::create_dir_all(&directory).unwrap();
+    for (name, content) in files {
+        fs::write(directory.join(name), content).unwrap();
+    }
+    directory
+}
+
+/// The lint warnings for `source`, without the file name.
+pub fn lint(test: &str, source: &str) -> Vec<String> {
+    let directory = directory(test, &[("lint.asm", source)]);
+    let warnings = lint_file(directory.join("lint.asm").to_str().unwrap(), &Options::default());
+    fs::remove_dir_all(&directory).unwrap();
+    warnings.unwrap()
+}
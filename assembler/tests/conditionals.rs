@@ -0,0 +1,46 @@
+/*
+Tests for `.if`, `.elif`, `.else` and `.endif`, and for the errors inside
+them.
+*/
+
+mod common;
+
+use assembler::Options;
+use common::{code, errors, program_with};
+
+const BRANCHES: &str = ".if @N > 3\nsetrc V0 1\n.elif @N > 1\nsetrc V0 2\n.else\nsetrc V0 3\n.endif\n";
+
+fn with_n(value: &str) -> Vec<u8> {
+    let options = Options { defines: vec![("N".to_string(), value.to_string())], ..Default::default() };
+    program_with(BRANCHES, &options).code
+}
+
+#[test]
+fn takes_the_first_true_branch() {
+    assert_eq!(with_n("5"), [0x60, 1]);
+    assert_eq!(with_n("2"), [0x60, 2]);
+    assert_eq!(with_n("0"), [0x60, 3]);
+    assert_eq!(code(".ifdef @N\nsetrc V0 1\n.endif\n.ifndef @N\nsetrc V0 2\n.endif\n"), [0x60, 2]);
+}
+
+#[test]
+fn a_failed_condition_is_reported_once() {
+    let errors = errors(BRANCHES);
+    assert_eq!(errors.len(), 1, "{:?}", errors);
+    assert!(errors[0].starts_with("Label not found: @N"), "{:?}", errors);
+}
+
+#[test]
+fn a_failed_elif_is_reported_once() {
+    let source = ".if 0\nsetrc V0 1\n.elif @M\nsetrc V0 2\n.else\nsetrc V0 3\n.endif\n";
+    let errors = errors(source);
+    assert_eq!(errors.len(), 1, "{:?}", errors);
+    assert!(errors[0].starts_with("Label not found: @M"), "{:?}", errors);
+}
+
+#[test]
+fn unmatched_directives_are_errors() {
+    assert_eq!(errors(".else\n"), [".else without .if"]);
+    assert_eq!(errors(".endif\n"), [".endif without .if"]);
+    assert_eq!(errors(".if 1\n.else\n.elif 1\n.endif\n"), [".elif after .else"]);
+}
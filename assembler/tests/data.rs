@@ -0,0 +1,61 @@
+/*
+Tests for the directives of the `.data` section: `.byte`, `.string`, `.fill`
+and `.org`, and the errors for data that overlaps or does not fit.
+*/
+
+mod common;
+
+use common::{data, error, errors};
+
+#[test]
+fn byte_takes_values_and_expressions() {
+    assert_eq!(data(".data\n.byte 1 0x20 'A' -1\n.byte 2 * 3\n"), [1, 0x20, b'A', 0xFF, 6]);
+    assert_eq!(data("@N = 4\n.data\n.byte @N @end\n@end\n"), [4, 2]);
+}
+
+#[test]
+fn string_ends_in_zero() {
+    assert_eq!(data(".data\n.string \"hi\"\n"), [b'h', b'i', 0]);
+    assert_eq!(data(".data\n.string \"a b\\t\\\"\\\\\"\n"), [b'a', b' ', b'b', b'\t', b'"', b'\\', 0]);
+    assert_eq!(data(".data\n.string \"\"\n"), [0]);
+}
+
+#[test]
+fn fill_repeats_a_value() {
+    assert_eq!(data(".data\n.fill 3 0xAA\n"), [0xAA, 0xAA, 0xAA]);
+    assert_eq!(data("@N = 2\n.data\n.fill @N\n.byte 1\n"), [0, 0, 1]);
+    assert_eq!(data(".data\n.fill 0 1\n.byte 2\n"), [2]);
+}
+
+#[test]
+fn org_moves_the_data() {
+    assert_eq!(data(".data\n.org 3\n.byte 1\n"), [0, 0, 0, 1]);
+    assert_eq!(data(".data\n.byte 1\n.org 4\n@x\n.byte @x\n"), [1, 0, 0, 0, 4]);
+    assert_eq!(data(".data\n.org 0xFF\n.byte 9\n").len(), 256);
+}
+
+#[test]
+fn rejects_negative_counts_and_addresses() {
+    assert_eq!(error(".data\n.fill -1\n"), "-1 can not be negative in .fill");
+    assert_eq!(error(".data\n.org -1\n"), "-1 can not be negative in .org");
+    assert_eq!(error("@N = 2\n.data\n.org @N - 3\n"), "-1 can not be negative in .org");
+}
+
+#[test]
+fn rejects_data_that_does_not_fit() {
+    assert_eq!(error(".data\n.fill 256\n"), "256 does not fit in 8 bits in .fill");
+    assert_eq!(error(".data\n.org 0xFF\n.byte 1 2\n"), "Data does not fit in the 256 bytes of memory");
+    assert_eq!(error(".data\n.byte 256\n"), "256 does not fit in 8 bits");
+}
+
+#[test]
+fn rejects_overlapping_data() {
+    assert_eq!(errors(".data\n.byte 1 2\n.org 1\n.byte 3\n"), ["Data overlaps at address 0x01"]);
+}
+
+#[test]
+fn directives_need_the_data_section() {
+    assert_eq!(error(".byte 1\n"), ".byte is only allowed in the .data section");
+    assert_eq!(error(".fill 1\n"), ".fill is only allowed in the .data section");
+    assert_eq!(error(".data\n.fill @LATER\n@LATER = 1\n"), "Label not found: @LATER (operands of .fill have to be defined before it)");
+}
@@ -0,0 +1,70 @@
+/*
+Tests for operand expressions: precedence, parentheses, unary minus and how
+the operands of a line are split.
+*/
+
+mod common;
+
+use common::{code, data, error};
+
+/// The byte `expr` evaluates to, loaded into V0 with `setrc`.
+fn value(expr: &str) -> u8 {
+    let code = code(&format!("@N = 5\nsetrc V0 {}\n", expr));
+    assert_eq!(code.len(), 2, "{}", expr);
+    code[1]
+}
+
+#[test]
+fn precedence() {
+    assert_eq!(value("1 + 2 * 3"), 7);
+    assert_eq!(value("8 - 4 / 2"), 6);
+    assert_eq!(value("1 << 2 + 1"), 8);
+    assert_eq!(value("6 & 3 | 8"), 10);
+    assert_eq!(value("1 | 6 ^ 3"), 5);
+    assert_eq!(value("2 < 3 == 1"), 1);
+    assert_eq!(value("~0 & 0x0F"), 0x0F);
+}
+
+#[test]
+fn parentheses() {
+    assert_eq!(value("(1 + 2) * 3"), 9);
+    assert_eq!(value("((1 + 2) * (3 + 1)) << 1"), 24);
+    assert_eq!(value("( @N - 1 )"), 4);
+    assert!(error("setrc V0 (1 + 2").starts_with("Unexpected end of expression"));
+}
+
+#[test]
+fn unary_minus() {
+    assert_eq!(value("-1"), 0xFF);
+    assert_eq!(value("-(2 * 3)"), 0xFA);
+    assert_eq!(value("5 - -1"), 6);
+    assert_eq!(value("-@N"), 0xFB);
+    assert_eq!(value("~-1"), 0);
+    assert_eq!(value("2 * -3"), 0xFA);
+}
+
+#[test]
+fn operands_split_at_spaces() {
+    assert_eq!(code("setrc 1 -1"), [0x61, 0xFF]);
+    assert_eq!(code("setrc V1 -1"), [0x61, 0xFF]);
+    assert_eq!(data(".data\n.byte 1 -1 2\n"), [1, 0xFF, 2]);
+}
+
+#[test]
+fn expressions_span_tokens() {
+    assert_eq!(value("@N - 1"), 4);
+    assert_eq!(value("@N *2"), 10);
+    assert_eq!(value("@N+ 1"), 6);
+    assert_eq!(code("@N = 5\nsetrc V1 @N - 1\nsetrc V2 @N"), [0x61, 0x04, 0x62, 0x05]);
+}
+
+#[test]
+fn extra_operands_are_errors() {
+    assert!(error("setrc V1 2 3").contains("3"));
+    assert!(error("@N = 5\nsetrc V1 @N -1").contains("-1"));
+}
+
+#[test]
+fn trailing_operator_is_an_error() {
+    assert!(error("setrc V0 1 +").starts_with("Unexpected end of expression"));
+}
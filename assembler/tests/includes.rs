@@ -0,0 +1,99 @@
+/*
+Tests for `.include`: where files are found, that every file is included once,
+and the errors for cycles and missing files.
+*/
+
+mod common;
+
+use std::fs;
+
+use assembler::{assemble_file, Diagnostic, Options};
+use common::directory;
+
+/// Assembles `main.asm` in a directory with `files`, returning the code or
+/// the diagnostics.
+fn assemble_main(test: &str, files: &[(&str, &str)], options: &Options) -> Result<Vec<u8>, Vec<Diagnostic>> {
+    let directory = directory(test, files);
+    let result = assemble_file(directory.join("main.asm").to_str().unwrap(), options);
+    fs::remove_dir_all(&directory).unwrap();
+    result.map(|assembled| assembled.program.code)
+}
+
+#[test]
+fn includes_files_next_to_the_including_file() {
+    let code = assemble_main("next-to", &[
+        ("main.asm", ".include \"lib.inc\"\nsetrc V1 @VALUE\n"),
+        ("lib.inc", "@VALUE = 2\nsetrc V0 1\n"),
+    ], &Options::default());
+    assert_eq!(code.unwrap(), [0x60, 1, 0x61, 2]);
+}
+
+#[test]
+fn searches_the_include_paths() {
+    let library = directory("include-path", &[("lib.inc", "setrc V0 1\n")]);
+    let options = Options { include_paths: vec![library.clone()], ..Default::default() };
+    let code = assemble_main("searches", &[("main.asm", ".include \"lib.inc\"\n")], &options);
+    fs::remove_dir_all(&library).unwrap();
+    assert_eq!(code.unwrap(), [0x60, 1]);
+}
+
+#[test]
+fn includes_every_file_once() {
+    let code = assemble_main("once", &[
+        ("main.asm", ".include \"a.inc\"\n.include \"b.inc\"\n.include \"a.inc\"\n"),
+        ("a.inc", ".include \"shared.inc\"\nsetrc V0 1\n"),
+        ("b.inc", ".include \"shared.inc\"\nsetrc V1 1\n"),
+        ("shared.inc", "setrc V2 1\n"),
+    ], &Options::default());
+    assert_eq!(code.unwrap(), [0x62, 1, 0x60, 1, 0x61, 1]);
+}
+
+#[test]
+fn rejects_include_cycles() {
+    let directory = directory("cycle", &[
+        ("main.asm", ".include \"a.inc\"\n"),
+        ("a.inc", ".include \"b.inc\"\n"),
+        ("b.inc", ".include \"a.inc\"\n"),
+    ]);
+    let canonical = fs::canonicalize(&directory).unwrap();
+    let diagnostics = assemble_file(directory.join("main.asm").to_str().unwrap(), &Options::default()).err().unwrap();
+    fs::remove_dir_all(&directory).unwrap();
+
+    assert_eq!(diagnostics.len(), 1, "{:?}", diagnostics);
+    let cycle = format!("{} -> {} -> {}", canonical.join("a.inc").display(), canonical.join("b.inc").display(), canonical.join("a.inc").display());
+    assert_eq!(diagnostics[0].message, format!("Include cycle: {}", cycle));
+    assert_eq!(diagnostics[0].file, Some(directory.join("b.inc").display().to_string()));
+    assert_eq!(diagnostics[0].line, Some(1));
+    assert_eq!(diagnostics[0].notes.len(), 2, "{:?}", diagnostics[0].notes);
+}
+
+#[test]
+fn a_file_including_itself_is_a_cycle() {
+    let directory = directory("self", &[("main.asm", ".include \"main.asm\"\n")]);
+    let main = fs::canonicalize(directory.join("main.asm")).unwrap();
+    let diagnostics = assemble_file(directory.join("main.asm").to_str().unwrap(), &Options::default()).err().unwrap();
+    fs::remove_dir_all(&directory).unwrap();
+
+    assert_eq!(diagnostics.len(), 1, "{:?}", diagnostics);
+    assert_eq!(diagnostics[0].message, format!("Include cycle: {} -> {}", main.display(), main.display()));
+}
+
+#[test]
+fn rejects_missing_files() {
+    let diagnostics = assemble_main("missing", &[
+        ("main.asm", "setrc V0 1\n.include \"lib.inc\"\n"),
+        ("lib.inc", ".include \"gone.inc\"\n"),
+    ], &Options::default()).unwrap_err();
+    assert_eq!(diagnostics.len(), 1, "{:?}", diagnostics);
+    assert_eq!(diagnostics[0].message, "Can not find gone.inc to include");
+    assert_eq!(diagnostics[0].line, Some(1));
+    // the file being assembled is not named in notes
+    assert_eq!(diagnostics[0].notes, ["included from line 2"]);
+}
+
+#[test]
+fn rejects_malformed_includes() {
+    let error = |source| assemble_main("malformed", &[("main.asm", source)], &Options::default()).unwrap_err().remove(0).message;
+    assert_eq!(error(".include lib.inc\n"), ".include expects a quoted file name, found lib.inc");
+    assert_eq!(error(".include\n"), ".include expects one file name");
+}
@@ -0,0 +1,66 @@
+/*
+Tests for the warnings of `assembler --lint`, one case that warns and one
+that does not for every lint.
+*/
+
+mod common;
+
+use common::lint;
+
+#[test]
+fn unreachable_code_after_jump() {
+    assert_eq!(lint("unreachable", "jump @end\nsetrc V0 1\n@end\n"), ["line 2: Unreachable code after jump"]);
+    assert_eq!(lint("unreachable", "@start\nsetpcr V0\nsetrc V0 1\njump @start\n"), ["line 3: Unreachable code after jump"]);
+
+    // a label or a skip before the jump makes the next instruction reachable
+    assert!(lint("reachable", "@loop\nifeq V0 V1\njump @loop\nsetrc V0 1\n").is_empty());
+    assert!(lint("reachable", "jump @next\n@next\nsetrc V0 1\n").is_empty());
+}
+
+#[test]
+fn unused_labels() {
+    assert_eq!(lint("unused", "@unused\nsetrc V0 1\n"), ["line 1: @unused is never used"]);
+    assert_eq!(lint("unused", "setrc V0 1\n.data\n@buffer\n.byte 0\n"), ["line 3: @buffer is never used"]);
+
+    assert!(lint("used", "@loop\nsetrc V0 1\nifeq V0 V1\njump @loop\n").is_empty());
+    assert!(lint("used", "setrm V0 @buffer\n.data\n@buffer\n.byte 0\n").is_empty());
+}
+
+#[test]
+fn vf_overwritten_by_a_flag() {
+    assert_eq!(
+        lint("clobber", "setrc VF 1\nadd V0 V1\n"),
+        ["line 1: VF is overwritten by the flag of the next instruction"]
+    );
+    assert_eq!(
+        lint("clobber", ".alias flag VF\nsetrr flag V2\nsub V0 V1\n"),
+        ["line 2: VF is overwritten by the flag of the next instruction"]
+    );
+
+    // the add reads VF, or nothing is written to VF
+    assert!(lint("no-clobber", "setrc VF 1\nadd V0 VF\n").is_empty());
+    assert!(lint("no-clobber", "inc V0\ndec V1\n").is_empty());
+    assert!(lint("no-clobber", "setrpc VF\nadd V0 V1\n").is_empty());
+}
+
+#[test]
+fn jumps_past_the_end() {
+    assert_eq!(
+        lint("past-end", "jump 5\n"),
+        ["line 1: Jump to 5 is past the end of the program (1 instructions)"]
+    );
+
+    // jumping to the end halts
+    assert!(lint("to-end", "jump @end\n@end\n").is_empty());
+    assert!(lint("to-end", "jump 1\n").is_empty());
+}
+
+#[test]
+fn skip_as_the_last_instruction() {
+    assert_eq!(
+        lint("last-skip", "setrc V0 1\nifneq V0 V1\n"),
+        ["line 2: Skip as the last instruction, there is nothing to skip"]
+    );
+
+    assert!(lint("skip", "ifneq V0 V1\nsetrc V0 1\n").is_empty());
+}
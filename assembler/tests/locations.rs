@@ -0,0 +1,25 @@
+/*
+Tests for where diagnostics and debug lines point: lines of the source, and
+definitions from the command line, which have no line.
+*/
+
+use assembler::{assemble, Options};
+use exe::SourceLine;
+
+#[test]
+fn debug_lines_start_at_one() {
+    let assembled = assemble("@N = 2\nsetrc V0 @N\n\nsetrc V1 1\n", &Options::default()).unwrap();
+    assert_eq!(assembled.debug.lines, [
+        SourceLine { file: None, line: Some(2) },
+        SourceLine { file: None, line: Some(4) },
+    ]);
+}
+
+#[test]
+fn command_line_definitions_have_no_line() {
+    let options = Options { defines: vec![("N".to_string(), "1 +".to_string())], ..Default::default() };
+    let diagnostics = assemble("setrc V0 @N\n", &options).err().unwrap();
+    assert_eq!(diagnostics.len(), 1, "{:?}", diagnostics);
+    assert_eq!(diagnostics[0].file.as_deref(), Some("-D N=1 +"));
+    assert_eq!(diagnostics[0].line, None);
+}
@@ -0,0 +1,27 @@
+/*
+Tests for how macro arguments are substituted into the body.
+*/
+
+mod common;
+
+use common::{code, data};
+
+#[test]
+fn substitutes_parameters_in_expressions() {
+    assert_eq!(code(".macro load r n\nsetrc \\r \\n+1\n.endm\nload V2 4\n"), [0x62, 0x05]);
+}
+
+#[test]
+fn leaves_string_literals_alone() {
+    let source = ".macro say n\n.data\n.string \"a\\n\"\n.byte \\n\n.endm\nsay 5\n";
+    assert_eq!(data(source), [b'a', b'\n', 0, 5]);
+
+    let source = ".macro say n\n.data\n.string \"\\\"\\n\\\"\"\n.endm\nsay 5\n";
+    assert_eq!(data(source), [b'"', b'\n', b'"', 0]);
+}
+
+#[test]
+fn leaves_character_literals_alone() {
+    let source = ".macro load t\nsetrc V0 '\\t'\nsetrc V1 '\\''+\\t\n.endm\nload 1\n";
+    assert_eq!(code(source), [0x60, b'\t', 0x61, b'\'' + 1]);
+}
@@ -0,0 +1,29 @@
+/*
+Tests for pseudo-instructions and the checks on their operands.
+*/
+
+mod common;
+
+use common::{code, error};
+
+#[test]
+fn inc_and_dec_use_vf() {
+    assert_eq!(code("inc V3"), [0x6F, 0x01, 0x90, 0x3F]);
+    assert_eq!(code("@R = 4\ndec @R"), [0x6F, 0x01, 0x91, 0x4F]);
+}
+
+#[test]
+fn inc_and_dec_can_not_change_vf() {
+    let message = "inc and dec use VF as scratch register and can not change VF";
+    assert_eq!(error("inc VF"), message);
+    assert_eq!(error("dec 15"), message);
+    assert_eq!(error(".alias flags VF\ninc flags"), message);
+    assert_eq!(error("@R = 15\ninc @R"), message);
+    assert_eq!(error("dec @R\n@R = 14 + 1"), message);
+}
+
+#[test]
+fn ret_is_not_an_instruction() {
+    assert_eq!(error("ret VE"), "Unknown instruction or extra operand: ret");
+    assert_eq!(code("setpcr VE"), [0x70, 0x0E]);
+}
@@ -0,0 +1,50 @@
+/*
+Tests for register operands: `V0` to `VF`, names from `.alias`, plain
+numbers and how `--strict` rejects them.
+*/
+
+mod common;
+
+use assembler::Options;
+use common::{code, error, errors_with, program_with};
+
+fn strict() -> Options {
+    Options { strict: true, ..Default::default() }
+}
+
+#[test]
+fn aliases_name_registers() {
+    assert_eq!(code(".alias counter V2\nsetrc counter 5\nadd counter v3\n"), [0x62, 5, 0x90, 0x23]);
+    assert_eq!(code(".alias low v1\n.alias high V1\nsub low high\n"), [0x91, 0x11]);
+    assert_eq!(code(".alias from V1\nmov V0 from\nmov from 7\n"), [0x40, 0x01, 0x61, 7]);
+}
+
+#[test]
+fn plain_numbers_are_registers_unless_strict() {
+    assert_eq!(code("setrc 2 5\nadd @R 3\n@R = 1\n"), [0x62, 5, 0x90, 0x13]);
+    let message = "Expected a register like V2 (plain numbers are not registers in strict mode)";
+    assert_eq!(errors_with("setrc 2 5\n", &strict()), [message]);
+    assert_eq!(errors_with("@R = 1\nadd @R V3\n", &strict()), [message]);
+}
+
+#[test]
+fn strict_accepts_register_names_and_aliases() {
+    let program = program_with(".alias counter V2\nsetrc counter 5\nadd V2 vF\n", &strict());
+    assert_eq!(program.code, [0x62, 5, 0x90, 0x2F]);
+}
+
+#[test]
+fn registers_are_not_values() {
+    assert_eq!(error("setrc V0 V1\n"), "V1 is a register, expected a value");
+    assert_eq!(error(".alias counter VA\nsetrc V0 counter\n"), "VA is a register, expected a value");
+}
+
+#[test]
+fn rejects_malformed_aliases() {
+    assert_eq!(error(".alias counter V2\n.alias counter V3\n"), "Alias counter is already defined");
+    assert_eq!(error(".alias V3 V2\n"), "Invalid alias name: V3");
+    assert_eq!(error(".alias a-b V2\n"), "Invalid alias name: a-b");
+    assert_eq!(error(".alias\n"), ".alias expects a name");
+    assert_eq!(error(".alias counter\n"), ".alias expects a name and a register like V2");
+    assert_eq!(error(".alias counter 2\n"), ".alias expects a name and a register like V2");
+}
@@ -0,0 +1,34 @@
+/*
+Tests for `assembler::run`, which returns the raw binary of a file.
+*/
+
+mod common;
+
+use std::fs;
+
+use common::directory;
+
+#[test]
+fn returns_the_code() {
+    let directory = directory("run-code", &[("code.asm", "setrc V1 2\nprint V1\n")]);
+    let binary = assembler::run(directory.join("code.asm").to_str().unwrap());
+    fs::remove_dir_all(&directory).unwrap();
+    assert_eq!(binary.unwrap(), [0x61, 0x02, 0x81, 0xFF]);
+}
+
+#[test]
+fn rejects_data() {
+    let directory = directory("run-data", &[("data.asm", "setrm V1 @x\n.data\n@x\n.byte 7\n")]);
+    let path = directory.join("data.asm").to_str().unwrap().to_string();
+    let binary = assembler::run(&path);
+    fs::remove_dir_all(&directory).unwrap();
+    assert_eq!(binary.unwrap_err(), format!("{}: the program has a .data section, which a raw binary can not hold", path));
+}
+
+#[test]
+fn reports_errors() {
+    let directory = directory("run-error", &[("error.asm", "jump @nowhere\n")]);
+    let binary = assembler::run(directory.join("error.asm").to_str().unwrap());
+    fs::remove_dir_all(&directory).unwrap();
+    assert!(binary.unwrap_err().contains("Label not found: @nowhere"));
+}
@@ -0,0 +1,43 @@
+/*
+Tests for the symbol table of assembled programs and the lint for constants
+that do not fit in it.
+*/
+
+mod common;
+
+use common::{lint, program};
+
+fn symbols(source: &str) -> Vec<(String, u8)> {
+    program(source).symbols.into_iter().map(|symbol| (symbol.name, symbol.value)).collect()
+}
+
+fn symbol(name: &str, value: u8) -> (String, u8) {
+    (name.to_string(), value)
+}
+
+#[test]
+fn keeps_labels_and_constants() {
+    let source = "@N = 3\n@start\nsetrc V0 @N\n.data\n@buffer\n.byte 0\n";
+    assert_eq!(symbols(source), [symbol("N", 3), symbol("buffer", 0), symbol("start", 0)]);
+}
+
+#[test]
+fn stores_negative_constants_as_twos_complement() {
+    let source = "@DOWN = -1\n@MIN = -128\n@MAX = 255\nsetrc V0 @DOWN\n";
+    assert_eq!(symbols(source), [symbol("DOWN", 0xFF), symbol("MAX", 0xFF), symbol("MIN", 0x80)]);
+}
+
+#[test]
+fn leaves_out_constants_that_do_not_fit() {
+    let source = "@BIG = 256\n@SMALL = -129\n@HALF = @BIG / 2\nsetrc V0 @HALF\nsetrc V1 @SMALL + 129\n";
+    assert_eq!(symbols(source), [symbol("HALF", 128)]);
+}
+
+#[test]
+fn lint_warns_about_constants_that_do_not_fit() {
+    let warnings = lint("symbols", "@BIG = 256\n@SMALL = -129\n@OK = -1\nsetrc V0 @BIG - @OK + @SMALL\n");
+    assert_eq!(warnings, [
+        "line 1: @BIG = 256 does not fit in 8 bits and is left out of the symbol table",
+        "line 2: @SMALL = -129 does not fit in 8 bits and is left out of the symbol table",
+    ]);
+}
@@ -0,0 +1,684 @@
+/*
+CHIP-8 core for `emulator --chip8`, running real CHIP-8 ROMs.
+
+This is the original instruction set of the COSMAC VIP interpreter, not the
+simplified ISA of the rest of the emulator:
+
+* 4 KB of memory, programs are loaded at 0x200 and the font at 0x000
+* registers V0-VF, VF is the flag register
+* I, a 12-bit address register
+* a stack of 16 return addresses for 2NNN and 00EE
+* delay and sound timers that count down at 60 Hz, see `tick`
+* a 64x32 monochrome display, sprites are drawn with XOR
+* a hex keypad with the keys 0-F
+
+The VIP behaviour is used where later interpreters differ: 8XY6 and 8XYE
+shift VY into VX, FX55 and FX65 move I past the last register, 8XY1 to 8XY3
+clear VF and BNNN jumps to NNN + V0. Sprites wrap around at the position they
+start at and are clipped at the edges.
+
+A jump to itself, the usual way for a CHIP-8 program to end, halts the core.
+*/
+
+use std::{
+    io::{self, Read, Write},
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    cpu::Cpu,
+    observer::Observer,
+    report_hits,
+    tui::Terminal,
+    watch::{Action, Hit, Target, Watcher, Watchpoint},
+    Step,
+};
+
+pub const MEMORY_SIZE: usize = 4096;
+/// Address programs are loaded at.
+pub const PROGRAM_START: usize = 0x200;
+/// Size of the largest ROM, which fills the memory after `PROGRAM_START`.
+pub const ROM_SIZE: usize = MEMORY_SIZE - PROGRAM_START;
+pub const WIDTH: usize = 64;
+pub const HEIGHT: usize = 32;
+
+/// Sprites of the hex digits 0-F, 5 bytes each, loaded at address 0.
+const FONT: [u8; 80] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+    0x20, 0x60, 0x20, 0x20, 0x70, // 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+    0x90, 0x90, 0xF0, 0x10, 0x10, // 4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+    0xF0, 0x10, 0x20, 0x40, 0x40, // 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+    0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
+    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+    0xE0, 0x90, 0x90, 0x90, 0xE0, // D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+];
+
+const STACK_SIZE: usize = 16;
+
+/// The state of a CHIP-8 computer, reporting what it does to `O`.
+pub struct Chip8<O: Observer = ()> {
+    memory: [u8; MEMORY_SIZE],
+    registers: [u8; 16],
+    i: u16,
+    pc: usize,
+    stack: Vec<usize>,
+    delay: u8,
+    sound: u8,
+    /// One row per line, the leftmost pixel in the highest bit.
+    display: [u64; HEIGHT],
+    /// Pressed keys, key K in bit K.
+    keys: u16,
+    /// Frames until each key that was pressed with `press` is released.
+    held: [u8; 16],
+    /// State of the xorshift generator for CXKK.
+    random: u32,
+    /// Bytes in the ROM.
+    rom_size: usize,
+    watcher: Watcher,
+    observer: O,
+}
+
+impl Chip8 {
+    /// A CHIP-8 computer with `rom` loaded at 0x200.
+    pub fn new(rom: &[u8]) -> Result<Self, String> {
+        Self::with_observer(rom, ())
+    }
+}
+
+impl<O: Observer> Chip8<O> {
+    /// A computer like `new` that calls `observer` while it runs. Memory
+    /// addresses in the callbacks are the 12-bit CHIP-8 addresses.
+    pub fn with_observer(rom: &[u8], observer: O) -> Result<Self, String> {
+        if rom.len() > ROM_SIZE {
+            return Err(format!("ROM is {} bytes but only {} fit in memory", rom.len(), ROM_SIZE));
+        }
+
+        let mut memory = [0; MEMORY_SIZE];
+        memory[..FONT.len()].copy_from_slice(&FONT);
+        memory[PROGRAM_START..PROGRAM_START + rom.len()].copy_from_slice(rom);
+
+        Ok(Self {
+            memory,
+            registers: [0; 16],
+            i: 0,
+            pc: PROGRAM_START,
+            stack: Vec::new(),
+            delay: 0,
+            sound: 0,
+            display: [0; HEIGHT],
+            keys: 0,
+            held: [0; 16],
+            random: 1,
+            rom_size: rom.len(),
+            watcher: Watcher::default(),
+            observer,
+        })
+    }
+
+    /// Seeds the random numbers of CXKK. The seed is fixed until this is
+    /// called, so runs can be repeated.
+    pub fn seed(&mut self, seed: u32) {
+        self.random = seed.max(1);
+    }
+
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    /// The value of register VX, `x` is 0 to 15.
+    pub fn register(&self, x: u8) -> u8 {
+        self.registers[x as usize & 0xF]
+    }
+
+    pub fn i(&self) -> u16 {
+        self.i
+    }
+
+    pub fn memory(&self, address: usize) -> u8 {
+        self.memory[address % MEMORY_SIZE]
+    }
+
+    /// Whether the pixel at (`x`, `y`) is on, (0, 0) is the top left.
+    pub fn pixel(&self, x: usize, y: usize) -> bool {
+        self.display[y % HEIGHT] >> (WIDTH - 1 - x % WIDTH) & 1 == 1
+    }
+
+    /// Presses or releases key 0-F of the keypad.
+    pub fn set_key(&mut self, key: u8, pressed: bool) {
+        let bit = 1 << (key & 0xF);
+        if pressed { self.keys |= bit } else { self.keys &= !bit }
+    }
+
+    /// Presses key 0-F of the keypad for `KEY_FRAMES` calls of `tick`.
+    /// Terminals do not report releases, so a held key is seen as repeated
+    /// presses.
+    pub fn press(&mut self, key: u8) {
+        self.set_key(key, true);
+        self.held[key as usize & 0xF] = KEY_FRAMES;
+    }
+
+    /// Whether the sound timer is running, which is when the VIP beeps.
+    pub fn sound(&self) -> bool {
+        self.sound > 0
+    }
+
+    pub fn observer(&self) -> &O {
+        &self.observer
+    }
+
+    pub fn observer_mut(&mut self) -> &mut O {
+        &mut self.observer
+    }
+
+    /// Takes the observer back, with whatever it collected.
+    pub fn into_observer(self) -> O {
+        self.observer
+    }
+
+    /// Stops or logs accesses to `watchpoint`'s target, see `take_hits`.
+    pub fn watch(&mut self, watchpoint: Watchpoint) {
+        self.watcher.watch(watchpoint);
+    }
+
+    /// The watched accesses since the last call, in the order they were made.
+    pub fn take_hits(&mut self) -> Vec<Hit> {
+        self.watcher.take_hits()
+    }
+
+    /// Counts the timers down and releases keys pressed with `press` that
+    /// were held long enough, to be called 60 times per second.
+    pub fn tick(&mut self) {
+        self.delay = self.delay.saturating_sub(1);
+        self.sound = self.sound.saturating_sub(1);
+        for key in 0..16 {
+            if self.held[key] == 1 {
+                self.set_key(key as u8, false);
+            }
+            self.held[key] = self.held[key].saturating_sub(1);
+        }
+    }
+
+    /// The display as a plain PBM image, 1 for pixels that are on.
+    pub fn pbm(&self) -> String {
+        let mut pbm = format!("P1\n{} {}\n", WIDTH, HEIGHT);
+        for y in 0..HEIGHT {
+            pbm.extend((0..WIDTH).map(|x| if self.pixel(x, y) { '1' } else { '0' }));
+            pbm.push('\n');
+        }
+        pbm
+    }
+
+    /// The display as text, two rows of pixels per line.
+    pub fn render(&self) -> String {
+        let mut text = String::new();
+        for y in (0..HEIGHT).step_by(2) {
+            text.extend((0..WIDTH).map(|x| match (self.pixel(x, y), self.pixel(x, y + 1)) {
+                (true, true) => '█',
+                (true, false) => '▀',
+                (false, true) => '▄',
+                (false, false) => ' ',
+            }));
+            text.push('\n');
+        }
+        text
+    }
+
+    /// Runs the instruction at the PC. Waiting for a key with FX0A runs the
+    /// same instruction again until a key is pressed.
+    pub fn step(&mut self) -> Result<Step, String> {
+        let pc = self.pc;
+        if pc + 1 >= MEMORY_SIZE {
+            return Err(format!("PC 0x{:03X} is outside of memory", pc));
+        }
+        let instruction = [self.memory[pc], self.memory[pc + 1]];
+        if instruction[0] & 0xF0 == 0x10 && usize::from(u16::from_be_bytes(instruction) & 0xFFF) == pc {
+            self.observer.on_halt(pc);
+            return Ok(Step::Halted);
+        }
+
+        self.observer.on_fetch(pc, instruction);
+        self.pc += 2;
+        self.execute(instruction)?;
+        self.observer.on_execute(pc, instruction, self.pc);
+        Ok(Step::Running)
+    }
+
+    fn execute(&mut self, instruction: [u8; 2]) -> Result<(), String> {
+        let n = [instruction[0] >> 4, instruction[0] & 0xF, instruction[1] >> 4, instruction[1] & 0xF];
+        let (x, y) = (n[1], n[2]);
+        let nnn = u16::from_be_bytes(instruction) & 0xFFF;
+        let kk = instruction[1];
+
+        match n {
+            [0x0, 0x0, 0xE, 0x0] => { // 00E0 CLS
+                self.display = [0; HEIGHT];
+            },
+            [0x0, 0x0, 0xE, 0xE] => { // 00EE RET
+                self.pc = self.stack.pop().ok_or_else(|| self.error("Return with an empty stack"))?;
+            },
+            [0x0, ..] => {}, // 0NNN SYS, machine code routines are not supported
+            [0x1, ..] => { // 1NNN JP NNN
+                self.pc = nnn as usize;
+            },
+            [0x2, ..] => { // 2NNN CALL NNN
+                if self.stack.len() == STACK_SIZE {
+                    return Err(self.error("Stack overflow"));
+                }
+                self.stack.push(self.pc);
+                self.pc = nnn as usize;
+            },
+            [0x3, ..] => { // 3XKK SE VX KK
+                if self.read_register(x) == kk {
+                    self.pc += 2;
+                }
+            },
+            [0x4, ..] => { // 4XKK SNE VX KK
+                if self.read_register(x) != kk {
+                    self.pc += 2;
+                }
+            },
+            [0x5, _, _, 0x0] => { // 5XY0 SE VX VY
+                if self.read_register(x) == self.read_register(y) {
+                    self.pc += 2;
+                }
+            },
+            [0x6, ..] => { // 6XKK LD VX KK
+                self.write_register(x, kk);
+            },
+            [0x7, ..] => { // 7XKK ADD VX KK, no carry
+                let value = self.read_register(x).wrapping_add(kk);
+                self.write_register(x, value);
+            },
+            [0x8, _, _, 0x0] => { // 8XY0 LD VX VY
+                let value = self.read_register(y);
+                self.write_register(x, value);
+            },
+            [0x8, _, _, 0x1] => { // 8XY1 OR VX VY
+                let value = self.read_register(x) | self.read_register(y);
+                self.write_register(x, value);
+                self.write_register(0xF, 0);
+            },
+            [0x8, _, _, 0x2] => { // 8XY2 AND VX VY
+                let value = self.read_register(x) & self.read_register(y);
+                self.write_register(x, value);
+                self.write_register(0xF, 0);
+            },
+            [0x8, _, _, 0x3] => { // 8XY3 XOR VX VY
+                let value = self.read_register(x) ^ self.read_register(y);
+                self.write_register(x, value);
+                self.write_register(0xF, 0);
+            },
+            [0x8, _, _, 0x4] => { // 8XY4 ADD VX VY, VF = carry
+                let (result, carry) = self.read_register(x).overflowing_add(self.read_register(y));
+                self.write_register(x, result);
+                self.write_register(0xF, carry as u8);
+            },
+            [0x8, _, _, 0x5] => { // 8XY5 SUB VX VY, VF = not borrow
+                let (result, borrow) = self.read_register(x).overflowing_sub(self.read_register(y));
+                self.write_register(x, result);
+                self.write_register(0xF, !borrow as u8);
+            },
+            [0x8, _, _, 0x6] => { // 8XY6 SHR VX VY, VF = shifted out bit
+                let value = self.read_register(y);
+                self.write_register(x, value >> 1);
+                self.write_register(0xF, value & 1);
+            },
+            [0x8, _, _, 0x7] => { // 8XY7 SUBN VX VY, VX = VY - VX, VF = not borrow
+                let (result, borrow) = self.read_register(y).overflowing_sub(self.read_register(x));
+                self.write_register(x, result);
+                self.write_register(0xF, !borrow as u8);
+            },
+            [0x8, _, _, 0xE] => { // 8XYE SHL VX VY, VF = shifted out bit
+                let value = self.read_register(y);
+                self.write_register(x, value << 1);
+                self.write_register(0xF, value >> 7);
+            },
+            [0x9, _, _, 0x0] => { // 9XY0 SNE VX VY
+                if self.read_register(x) != self.read_register(y) {
+                    self.pc += 2;
+                }
+            },
+            [0xA, ..] => { // ANNN LD I NNN
+                self.i = nnn;
+            },
+            [0xB, ..] => { // BNNN JP V0 NNN
+                self.pc = nnn as usize + self.read_register(0) as usize;
+            },
+            [0xC, ..] => { // CXKK RND VX KK
+                let value = self.random() & kk;
+                self.write_register(x, value);
+            },
+            [0xD, ..] => { // DXYN DRW VX VY N, VF = collision
+                let (vx, vy) = (self.read_register(x), self.read_register(y));
+                self.draw(vx as usize, vy as usize, n[3] as usize);
+            },
+            [0xE, _, 0x9, 0xE] => { // EX9E SKP VX
+                if self.keys >> (self.read_register(x) & 0xF) & 1 == 1 {
+                    self.pc += 2;
+                }
+            },
+            [0xE, _, 0xA, 0x1] => { // EXA1 SKNP VX
+                if self.keys >> (self.read_register(x) & 0xF) & 1 == 0 {
+                    self.pc += 2;
+                }
+            },
+            [0xF, _, 0x0, 0x7] => { // FX07 LD VX DT
+                self.write_register(x, self.delay);
+            },
+            [0xF, _, 0x0, 0xA] => { // FX0A LD VX K, waits for a key
+                match (0..16).find(|key| self.keys >> key & 1 == 1) {
+                    Some(key) => self.write_register(x, key),
+                    None => self.pc -= 2,
+                }
+            },
+            [0xF, _, 0x1, 0x5] => { // FX15 LD DT VX
+                self.delay = self.read_register(x);
+            },
+            [0xF, _, 0x1, 0x8] => { // FX18 LD ST VX
+                self.sound = self.read_register(x);
+            },
+            [0xF, _, 0x1, 0xE] => { // FX1E ADD I VX
+                self.i = (self.i + self.read_register(x) as u16) & 0xFFF;
+            },
+            [0xF, _, 0x2, 0x9] => { // FX29 LD F VX, I = sprite of digit VX
+                self.i = (self.read_register(x) & 0xF) as u16 * 5;
+            },
+            [0xF, _, 0x3, 0x3] => { // FX33 LD B VX, BCD of VX at I
+                let value = self.read_register(x);
+                for (offset, digit) in [value / 100, value / 10 % 10, value % 10].into_iter().enumerate() {
+                    self.write(self.i as usize + offset, digit);
+                }
+            },
+            [0xF, _, 0x5, 0x5] => { // FX55 LD [I] VX, stores V0 to VX
+                for register in 0..=x {
+                    let value = self.read_register(register);
+                    self.write(self.i as usize + register as usize, value);
+                }
+                self.i = (self.i + x as u16 + 1) & 0xFFF;
+            },
+            [0xF, _, 0x6, 0x5] => { // FX65 LD VX [I], loads V0 to VX
+                for register in 0..=x {
+                    let value = self.read(self.i as usize + register as usize);
+                    self.write_register(register, value);
+                }
+                self.i = (self.i + x as u16 + 1) & 0xFFF;
+            },
+            _ => {
+                return Err(self.error(&format!("Unknown instruction {:02X}{:02X}", instruction[0], instruction[1])));
+            },
+        }
+        Ok(())
+    }
+
+    /// Draws the `height` rows of the sprite at I with its top left corner
+    /// at (`x`, `y`).
+    fn draw(&mut self, x: usize, y: usize, height: usize) {
+        let (x, y) = (x % WIDTH, y % HEIGHT);
+        let mut collision = false;
+        for row in 0..height.min(HEIGHT - y) {
+            let sprite = self.read(self.i as usize + row) as u64;
+            // the sprite starts at bit 63 - x, pixels past the right edge are cut off
+            let pixels = (sprite << (WIDTH - 8)) >> x;
+            collision |= self.display[y + row] & pixels != 0;
+            self.display[y + row] ^= pixels;
+        }
+        self.write_register(0xF, collision as u8);
+    }
+
+    fn read_register(&mut self, x: u8) -> u8 {
+        let value = self.registers[x as usize];
+        self.watcher.check(Target::Register(x), false, value, value, self.pc - 2);
+        value
+    }
+
+    fn write_register(&mut self, x: u8, value: u8) {
+        self.watcher.check(Target::Register(x), true, self.registers[x as usize], value, self.pc - 2);
+        self.registers[x as usize] = value;
+    }
+
+    fn read(&mut self, address: usize) -> u8 {
+        let address = address % MEMORY_SIZE;
+        let value = self.memory[address];
+        self.observer.on_mem_read(address, value);
+        self.watcher.check(Target::Memory(address as u16), false, value, value, self.pc - 2);
+        value
+    }
+
+    fn write(&mut self, address: usize, value: u8) {
+        let address = address % MEMORY_SIZE;
+        let old = self.memory[address];
+        self.observer.on_mem_write(address, old, value);
+        self.watcher.check(Target::Memory(address as u16), true, old, value, self.pc - 2);
+        self.memory[address] = value;
+    }
+
+    /// The next number of the xorshift generator.
+    fn random(&mut self) -> u8 {
+        self.random ^= self.random << 13;
+        self.random ^= self.random >> 17;
+        self.random ^= self.random << 5;
+        (self.random >> 24) as u8
+    }
+
+    /// An error at the instruction that is running.
+    fn error(&self, message: &str) -> String {
+        format!("{} at PC 0x{:03X}", message, self.pc - 2)
+    }
+}
+
+impl<O: Observer> Cpu for Chip8<O> {
+    fn step(&mut self) -> Result<Step, String> {
+        Chip8::step(self)
+    }
+
+    fn pc(&self) -> usize {
+        self.pc
+    }
+
+    fn register(&self, x: u8) -> u8 {
+        Chip8::register(self, x)
+    }
+
+    fn memory_size(&self) -> usize {
+        MEMORY_SIZE
+    }
+
+    fn byte(&self, address: usize) -> u8 {
+        self.memory(address)
+    }
+
+    fn layout(&self) -> (usize, usize, usize) {
+        (PROGRAM_START, 2, self.rom_size.div_ceil(2))
+    }
+
+    fn disassemble(&self, pc: usize) -> Option<String> {
+        let code = [*self.memory.get(pc)?, *self.memory.get(pc + 1)?];
+        Some(disassemble(code).unwrap_or_else(|| format!("{:02X}{:02X}", code[0], code[1])))
+    }
+
+    fn watch(&mut self, watchpoint: Watchpoint) {
+        Chip8::watch(self, watchpoint);
+    }
+
+    fn take_hits(&mut self) -> Vec<Hit> {
+        Chip8::take_hits(self)
+    }
+
+    /// I, the timers, the stack depth and the display.
+    fn details(&self) -> Vec<String> {
+        let mut lines = vec![format!(
+            "I {:03X}  DT {:02X}  ST {:02X}  stack {}", self.i, self.delay, self.sound, self.stack.len()
+        )];
+        lines.extend(self.render().lines().map(str::to_string));
+        lines
+    }
+
+    /// The 256 bytes from a little before I, where the sprites and digits
+    /// the program works on usually are.
+    fn memory_view(&self) -> usize {
+        (self.i as usize & !0xF).saturating_sub(0x40).min(MEMORY_SIZE - 0x100)
+    }
+
+    fn steps_per_frame(&self) -> Option<usize> {
+        Some(STEPS_PER_FRAME)
+    }
+
+    fn tick(&mut self) {
+        Chip8::tick(self);
+    }
+
+    fn press(&mut self, key: u8) {
+        Chip8::press(self, key);
+    }
+}
+
+/// The instruction in the mnemonics of Cowgod's reference, like
+/// `LD V1, 0x2A`, or `None` if it is unknown.
+pub fn disassemble(instruction: [u8; 2]) -> Option<String> {
+    let n = [instruction[0] >> 4, instruction[0] & 0xF, instruction[1] >> 4, instruction[1] & 0xF];
+    let (x, y) = (n[1], n[2]);
+    let nnn = u16::from_be_bytes(instruction) & 0xFFF;
+    let kk = instruction[1];
+
+    let text = match n {
+        [0x0, 0x0, 0xE, 0x0] => "CLS".to_string(),
+        [0x0, 0x0, 0xE, 0xE] => "RET".to_string(),
+        [0x0, ..] => format!("SYS 0x{:03X}", nnn),
+        [0x1, ..] => format!("JP 0x{:03X}", nnn),
+        [0x2, ..] => format!("CALL 0x{:03X}", nnn),
+        [0x3, ..] => format!("SE V{:X}, 0x{:02X}", x, kk),
+        [0x4, ..] => format!("SNE V{:X}, 0x{:02X}", x, kk),
+        [0x5, _, _, 0x0] => format!("SE V{:X}, V{:X}", x, y),
+        [0x6, ..] => format!("LD V{:X}, 0x{:02X}", x, kk),
+        [0x7, ..] => format!("ADD V{:X}, 0x{:02X}", x, kk),
+        [0x8, _, _, operation] => {
+            let mnemonic = match operation {
+                0x0 => "LD",
+                0x1 => "OR",
+                0x2 => "AND",
+                0x3 => "XOR",
+                0x4 => "ADD",
+                0x5 => "SUB",
+                0x6 => "SHR",
+                0x7 => "SUBN",
+                0xE => "SHL",
+                _ => return None,
+            };
+            format!("{} V{:X}, V{:X}", mnemonic, x, y)
+        },
+        [0x9, _, _, 0x0] => format!("SNE V{:X}, V{:X}", x, y),
+        [0xA, ..] => format!("LD I, 0x{:03X}", nnn),
+        [0xB, ..] => format!("JP V0, 0x{:03X}", nnn),
+        [0xC, ..] => format!("RND V{:X}, 0x{:02X}", x, kk),
+        [0xD, ..] => format!("DRW V{:X}, V{:X}, {}", x, y, n[3]),
+        [0xE, _, 0x9, 0xE] => format!("SKP V{:X}", x),
+        [0xE, _, 0xA, 0x1] => format!("SKNP V{:X}", x),
+        [0xF, _, 0x0, 0x7] => format!("LD V{:X}, DT", x),
+        [0xF, _, 0x0, 0xA] => format!("LD V{:X}, K", x),
+        [0xF, _, 0x1, 0x5] => format!("LD DT, V{:X}", x),
+        [0xF, _, 0x1, 0x8] => format!("LD ST, V{:X}", x),
+        [0xF, _, 0x1, 0xE] => format!("ADD I, V{:X}", x),
+        [0xF, _, 0x2, 0x9] => format!("LD F, V{:X}", x),
+        [0xF, _, 0x3, 0x3] => format!("LD B, V{:X}", x),
+        [0xF, _, 0x5, 0x5] => format!("LD [I], V{:X}", x),
+        [0xF, _, 0x6, 0x5] => format!("LD V{:X}, [I]", x),
+        _ => return None,
+    };
+    Some(text)
+}
+
+/// Instructions per frame, about the speed of the VIP.
+pub const STEPS_PER_FRAME: usize = 11;
+const FRAME: Duration = Duration::from_micros(16_667);
+/// Frames a key stays pressed after the terminal sent it.
+const KEY_FRAMES: u8 = 15;
+
+/// Keyboard keys of the keypad keys 0-F, laid out like the VIP keypad:
+///
+/// ```text
+/// 1 2 3 4      1 2 3 C
+/// q w e r      4 5 6 D
+/// a s d f      7 8 9 E
+/// z x c v      A 0 B F
+/// ```
+const KEYS: [u8; 16] = *b"x123qweasdzc4rfv";
+
+/// Runs the ROM for at most `cycles` instructions without a terminal, the
+/// same way every time, and prints the display. Watchpoints are reported on
+/// stderr and stop the run like they stop `Machine`.
+pub(crate) fn run_headless(chip8: &mut Chip8, cycles: usize) -> Result<(), String> {
+    for cycle in 0..cycles {
+        if chip8.step()? == Step::Halted || report_hits(chip8) {
+            break;
+        }
+        if cycle % STEPS_PER_FRAME == STEPS_PER_FRAME - 1 {
+            chip8.tick();
+        }
+    }
+    print!("{}", chip8.render());
+    Ok(())
+}
+
+/// Shows the ROM in the terminal at the speed of the VIP until Esc is
+/// pressed. The last watchpoint hit is shown below the display, and a
+/// watchpoint that stops ends the run like halting does.
+pub(crate) fn run_terminal(chip8: &mut Chip8) -> Result<(), String> {
+    let seed = SystemTime::now().duration_since(UNIX_EPOCH).map_or(1, |time| time.subsec_nanos());
+    chip8.seed(seed);
+
+    let _terminal = Terminal::new(0)?;
+    let mut stdin = io::stdin();
+    let mut stdout = io::stdout();
+    let mut input = [0; 16];
+    let mut state = "";
+    let mut hit = String::new();
+    let mut frame = Instant::now();
+    loop {
+        let count = stdin.read(&mut input).map_err(|e| e.to_string())?;
+        for byte in &input[..count] {
+            if *byte == 0x1b {
+                return Ok(());
+            }
+            if let Some(key) = KEYS.iter().position(|key| key == &byte.to_ascii_lowercase()) {
+                chip8.press(key as u8);
+            }
+        }
+
+        let beeping = chip8.sound();
+        for _ in 0..STEPS_PER_FRAME {
+            if !state.is_empty() {
+                break;
+            }
+            if chip8.step()? == Step::Halted {
+                state = " (halted)";
+            }
+            for watched in chip8.take_hits() {
+                hit = format!("  watchpoint: {}", watched);
+                if watched.watchpoint.action == Action::Stop {
+                    state = " (stopped)";
+                }
+            }
+        }
+        chip8.tick();
+
+        let mut screen = format!("\x1b[H{}", chip8.render().replace('\n', "\x1b[K\r\n"));
+        screen.push_str(&format!("PC 0x{:03X}{}{}  Esc: quit\x1b[K", chip8.pc(), state, hit));
+        if chip8.sound() && !beeping {
+            screen.push('\x07');
+        }
+        stdout.write_all(screen.as_bytes()).and_then(|_| stdout.flush()).map_err(|e| e.to_string())?;
+
+        frame += FRAME;
+        thread::sleep(frame.saturating_duration_since(Instant::now()));
+    }
+}
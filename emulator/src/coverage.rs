@@ -0,0 +1,110 @@
+/*
+Records which instructions ran and which way the skips went, and writes it as
+an lcov tracefile for `genhtml` and coverage services.
+
+The instructions are mapped to source lines with the debug info of
+`assembler -g`. A line counts the executions of its most executed
+instruction, so a pseudo-instruction that expands to several instructions
+does not count more than once. Every `ifeq`, `ifneq` and `ifle` is a branch
+with two ways: it did not skip (0) or it skipped the next instruction (1).
+*/
+
+use std::collections::BTreeMap;
+
+use exe::{DebugInfo, Executable};
+
+use crate::observer::Observer;
+
+#[derive(Debug, Clone, Default)]
+pub struct Coverage {
+    /// Executions per PC.
+    counts: Vec<u64>,
+    /// Per PC, how often it went on to the next instruction and how often it
+    /// skipped one.
+    ways: Vec<[u64; 2]>,
+}
+
+/// Coverage of one source line.
+#[derive(Debug, Default)]
+struct Line {
+    count: u64,
+    /// Skips on the line as (PC, not skipped, skipped).
+    branches: Vec<(usize, u64, u64)>,
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the counts of another run of the same program.
+    pub fn merge(&mut self, other: &Coverage) {
+        if self.counts.len() < other.counts.len() {
+            self.counts.resize(other.counts.len(), 0);
+            self.ways.resize(other.counts.len(), [0, 0]);
+        }
+        for (pc, count) in other.counts.iter().enumerate() {
+            self.counts[pc] += count;
+            self.ways[pc][0] += other.ways[pc][0];
+            self.ways[pc][1] += other.ways[pc][1];
+        }
+    }
+
+    /// The lcov records of `program`, one per source file. Instructions
+    /// without a source line in the debug info are left out.
+    pub fn lcov(&self, program: &Executable, debug: &DebugInfo) -> String {
+        let mut files: BTreeMap<&str, BTreeMap<usize, Line>> = BTreeMap::new();
+        for (pc, source) in debug.lines.iter().enumerate() {
+            let Some(file) = source.file.as_deref().or(debug.source.as_deref()) else { continue };
+            let Some(number) = source.line else { continue };
+            let line = files.entry(file).or_default().entry(number).or_default();
+            let count = self.counts.get(pc).copied().unwrap_or(0);
+            line.count = line.count.max(count);
+            if program.code.get(pc * 2).is_some_and(|high| (0x1..=0x3).contains(&(high >> 4))) {
+                let [next, skip] = self.ways.get(pc).copied().unwrap_or_default();
+                line.branches.push((pc, next, skip));
+            }
+        }
+
+        let mut lcov = String::new();
+        for (file, lines) in &files {
+            lcov.push_str(&format!("TN:\nSF:{}\n", file));
+
+            let (mut found, mut hit) = (0, 0);
+            for (number, line) in lines {
+                for (pc, next, skip) in &line.branches {
+                    for (way, taken) in [next, skip].into_iter().enumerate() {
+                        let taken = if line.count == 0 { "-".to_string() } else { taken.to_string() };
+                        lcov.push_str(&format!("BRDA:{},{},{},{}\n", number, pc, way, taken));
+                    }
+                    found += 2;
+                    hit += (*next > 0) as usize + (*skip > 0) as usize;
+                }
+            }
+            lcov.push_str(&format!("BRF:{}\nBRH:{}\n", found, hit));
+
+            for (number, line) in lines {
+                lcov.push_str(&format!("DA:{},{}\n", number, line.count));
+            }
+            let hit = lines.values().filter(|line| line.count > 0).count();
+            lcov.push_str(&format!("LF:{}\nLH:{}\nend_of_record\n", lines.len(), hit));
+        }
+        lcov
+    }
+}
+
+impl Observer for Coverage {
+    fn on_execute(&mut self, pc: usize, _: [u8; 2], next: usize) {
+        if self.counts.len() <= pc {
+            self.counts.resize(pc + 1, 0);
+            self.ways.resize(pc + 1, [0, 0]);
+        }
+        self.counts[pc] += 1;
+        if next == pc + 1 {
+            self.ways[pc][0] += 1;
+        }
+        else if next == pc + 2 {
+            self.ways[pc][1] += 1;
+        }
+    }
+}
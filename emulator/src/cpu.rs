@@ -0,0 +1,63 @@
+/*
+What the TUI and the watchpoints need from an emulated computer, so they work
+the same on `Machine` and on the CHIP-8 core.
+
+The cores number their PCs differently: the machine counts instructions from
+0, CHIP-8 counts bytes from 0x200. `layout` tells the TUI how to list the
+instructions of the program either way.
+*/
+
+use crate::{watch::{Hit, Watchpoint}, Step};
+
+pub trait Cpu {
+    /// Runs the instruction at the PC.
+    fn step(&mut self) -> Result<Step, String>;
+
+    fn pc(&self) -> usize;
+
+    /// The value of register VX, `x` is 0 to 15.
+    fn register(&self, x: u8) -> u8;
+
+    /// Bytes of memory, addressed from 0.
+    fn memory_size(&self) -> usize;
+
+    /// The byte at `address`, which is below `memory_size`.
+    fn byte(&self, address: usize) -> u8;
+
+    /// The PC of the first instruction, the PCs between two instructions and
+    /// the number of instructions in the program.
+    fn layout(&self) -> (usize, usize, usize);
+
+    /// The instruction at `pc` in assembly, or as hex digits if it is
+    /// unknown. `None` if there is no instruction at `pc`.
+    fn disassemble(&self, pc: usize) -> Option<String>;
+
+    /// Stops or logs accesses to `watchpoint`'s target, see `take_hits`.
+    fn watch(&mut self, watchpoint: Watchpoint);
+
+    /// The watched accesses since the last call, in the order they were made.
+    fn take_hits(&mut self) -> Vec<Hit>;
+
+    /// More state to show below the registers, one line per entry.
+    fn details(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// The first memory address to show, so a window of it can follow
+    /// what the program works on.
+    fn memory_view(&self) -> usize {
+        0
+    }
+
+    /// Instructions per 60 Hz frame for cores with timers or input that
+    /// count frames, see `tick`.
+    fn steps_per_frame(&self) -> Option<usize> {
+        None
+    }
+
+    /// Ends a frame of `steps_per_frame` instructions.
+    fn tick(&mut self) {}
+
+    /// Presses key 0-F of the keypad for a moment, for cores that have one.
+    fn press(&mut self, _key: u8) {}
+}
@@ -0,0 +1,53 @@
+/*
+Turns machine code back into assembly that the assembler accepts. Registers
+are written `V0` to `VF` and constants in hex, so assembling the output gives
+the same bytes again.
+
+Encodings the assembler never produces, like `7123` where `setpcr` only uses
+the last nibble, are reported as unknown even if the emulator would run them.
+*/
+
+/// Disassembles one instruction, or returns `None` if it is not one the
+/// assembler can produce.
+pub fn instruction(instruction: [u8; 2]) -> Option<String> {
+    let [high, low] = instruction;
+    let n = [high >> 4, high & 0x0F, low >> 4, low & 0x0F];
+
+    let text = match n {
+        [0x0, 0x0, ..] => format!("jump 0x{:02X}", low),
+        [0x1, 0x0, x, y] => format!("ifeq V{:X} V{:X}", x, y),
+        [0x2, 0x0, x, y] => format!("ifneq V{:X} V{:X}", x, y),
+        [0x3, 0x0, x, y] => format!("ifle V{:X} V{:X}", x, y),
+        [0x4, 0x0, x, y] => format!("setrr V{:X} V{:X}", x, y),
+        [0x4, 0x1, 0x0, x] => format!("setrpc V{:X}", x),
+        [0x5, x, ..] => format!("setrm V{:X} 0x{:02X}", x, low),
+        [0x6, x, ..] => format!("setrc V{:X} 0x{:02X}", x, low),
+        [0x7, 0x0, 0x0, x] => format!("setpcr V{:X}", x),
+        [0x8, x, ..] => format!("setmr V{:X} 0x{:02X}", x, low),
+        [0x9, 0x0, x, y] => format!("add V{:X} V{:X}", x, y),
+        [0x9, 0x1, x, y] => format!("sub V{:X} V{:X}", x, y),
+        [0xA, 0x0, x, y] => format!("and V{:X} V{:X}", x, y),
+        [0xA, 0x1, x, y] => format!("or V{:X} V{:X}", x, y),
+        [0xA, 0x2, x, y] => format!("xor V{:X} V{:X}", x, y),
+        [0xA, 0x3, x, 0x0] => format!("not V{:X}", x),
+        _ => return None,
+    };
+    Some(text)
+}
+
+/// Disassembles a program, one instruction per line.
+pub fn disassemble(code: &[u8]) -> Result<String, String> {
+    if !code.len().is_multiple_of(2) {
+        return Err(format!("Code is {} bytes, instructions are 2 bytes each", code.len()));
+    }
+
+    let mut text = String::new();
+    for (pc, pair) in code.chunks(2).enumerate() {
+        let pair = [pair[0], pair[1]];
+        let line = instruction(pair)
+            .ok_or_else(|| format!("Unknown instruction {:02X}{:02X} at PC {}", pair[0], pair[1], pc))?;
+        text.push_str(&line);
+        text.push('\n');
+    }
+    Ok(text)
+}
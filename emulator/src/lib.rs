@@ -31,75 +31,303 @@ Simplified instructionset based on CHIP-8
  */
 
 
-#![feature(slice_as_chunks)]
-#![feature(iter_array_chunks)]
+pub mod chip8;
+pub mod coverage;
+pub mod cpu;
+pub mod disassembler;
+pub mod loader;
+pub mod observer;
+pub mod profile;
+pub mod tui;
+pub mod watch;
+
 use std::fmt::Display;
 use std::fs;
 
-pub fn run(path: &str) {
-    let input = fs::read(path).expect("Bad path");
-    let program_size = (input.len() / 2) as u8;
-    let rom = u8Array::<512>::from(input);
-
-    /* 
-        .expect("Bad path")
-        .split_whitespace()
-        .collect::<String>()
-        .chars()
-        .array_chunks::<2>()
-        .map(|char| { 
-            program_size += 1; 
-            (char[0].to_digit(16).expect("not hex") * 16 + char[1].to_digit(16).expect("not hex")) as u8 
-        })
-        .collect::<Vec<u8>>() */
+use chip8::Chip8;
+use coverage::Coverage;
+use cpu::Cpu;
+use exe::Executable;
+use loader::Format;
+use observer::Observer;
+use profile::Profile;
+use watch::{Action, Hit, Target, Watcher, Watchpoint};
 
-    println!("Program size: {}", program_size);
-    println!("ROM: {}", rom);
+/// Size of the program ROM in bytes, two bytes per instruction.
+pub const ROM_SIZE: usize = 512;
 
-    let mut registers = u8Array::from([0u8; 16]);
+pub use exe::MEMORY_SIZE;
+
+/// Settings for running a program.
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// Program format, detected from the file content if `None`.
+    pub format: Option<Format>,
+    /// Print an instruction profile to stderr when the program halts.
+    pub profile: bool,
+    /// Write the profile as folded stacks for flamegraph tools to this file.
+    pub folded: Option<String>,
+    /// Write an lcov coverage report to this file, needs debug info.
+    pub coverage: Option<String>,
+    /// Watchpoints to stop at or log.
+    pub watchpoints: Vec<Watchpoint>,
+    /// Run in the full-screen terminal UI instead of printing the output.
+    pub tui: bool,
+    /// Run the program as a CHIP-8 ROM.
+    pub chip8: bool,
+    /// Stop after this many instructions, for CHIP-8 ROMs without the
+    /// terminal display.
+    pub cycles: Option<usize>,
+    /// Write the CHIP-8 display to this file as a PBM image at the end.
+    pub snapshot: Option<String>,
+}
 
-    let mut memory = u8Array::from([0u8; 256]);
+/// Runs the program at `path`.
+pub fn run(path: &str, options: &Options) -> Result<(), String> {
+    let input = fs::read(path).map_err(|e| e.to_string())?;
+    if options.chip8 {
+        // CHIP-8 ROMs are raw binaries, which the detection could take for text
+        let program = loader::load(&input, Some(options.format.unwrap_or(Format::Raw)), chip8::ROM_SIZE)?;
+        let mut chip8 = Chip8::new(&program.code)?;
+        watch(&mut chip8, &options.watchpoints)?;
+        let result = match (options.tui, options.cycles) {
+            (true, _) => tui::run(&program, &mut chip8),
+            (false, Some(cycles)) => chip8::run_headless(&mut chip8, cycles),
+            (false, None) => chip8::run_terminal(&mut chip8),
+        };
+        if let Some(path) = &options.snapshot {
+            fs::write(path, chip8.pbm()).map_err(|e| format!("{}: {}", path, e))?;
+        }
+        return result;
+    }
+    let program = loader::load(&input, options.format, ROM_SIZE)?;
+    if options.tui {
+        let mut machine = Machine::new(&program)?;
+        watch(&mut machine, &options.watchpoints)?;
+        return tui::run(&program, &mut machine);
+    }
+    if options.coverage.is_some() && program.debug.is_none() {
+        return Err("--coverage needs debug info, assemble the program with -g --format exe".to_string());
+    }
+    let profile = (options.profile || options.folded.is_some()).then(Profile::new);
+    let coverage = options.coverage.is_some().then(Coverage::new);
+    let mut machine = Machine::with_observer(&program, (profile, coverage))?;
+    watch(&mut machine, &options.watchpoints)?;
 
-    let mut pc: u8 = 0;
+    println!("Program size: {}", machine.program_size);
+    println!("ROM: {}", machine.rom);
 
     loop {
-        if pc >= program_size {
+        match machine.step()? {
+            Step::Running => {},
+            Step::Output(value) => println!("{}", value),
+            Step::Halted => break,
+        }
+        if report_hits(&mut machine) {
             break;
         }
+    }
+
+    let (profile, coverage) = machine.into_observer();
+    if let Some(profile) = profile {
+        if options.profile {
+            eprint!("{}", profile.report(&program));
+        }
+        if let Some(folded) = &options.folded {
+            fs::write(folded, profile.folded(&program)).map_err(|e| format!("{}: {}", folded, e))?;
+        }
+    }
+    if let (Some(coverage), Some(path), Some(debug)) = (coverage, &options.coverage, &program.debug) {
+        fs::write(path, coverage.lcov(&program, debug)).map_err(|e| format!("{}: {}", path, e))?;
+    }
+
+    Ok(())
+}
+
+/// Sets the watchpoints on `cpu`, checking that their addresses are in its
+/// memory.
+fn watch(cpu: &mut impl Cpu, watchpoints: &[Watchpoint]) -> Result<(), String> {
+    for watchpoint in watchpoints {
+        if let Target::Memory(address) = watchpoint.target {
+            if address as usize >= cpu.memory_size() {
+                return Err(format!("Watchpoint address 0x{:02X} is outside the {} byte memory", address, cpu.memory_size()));
+            }
+        }
+        cpu.watch(*watchpoint);
+    }
+    Ok(())
+}
+
+/// Prints the watched accesses of the last step to stderr, and the state
+/// of `cpu` if one of them stops it. Returns whether to stop.
+pub(crate) fn report_hits(cpu: &mut impl Cpu) -> bool {
+    let mut stop = false;
+    for hit in cpu.take_hits() {
+        eprintln!("watchpoint: {}", hit);
+        stop |= hit.watchpoint.action == Action::Stop;
+    }
+    if stop {
+        let registers: String = (0..16).map(|x| format!("{:02X} ", cpu.register(x))).collect();
+        eprintln!("Stopped at PC {}", cpu.pc());
+        eprintln!("Registers: {}", registers);
+    }
+    stop
+}
+
+/// What happened in one step of the machine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    Running,
+    /// The instruction wrote a value to the output address 0xFF.
+    Output(u8),
+    /// The PC is past the end of the program.
+    Halted,
+}
+
+/// The state of the emulated computer, reporting what it does to `O`.
+pub struct Machine<O: Observer = ()> {
+    rom: u8Array<ROM_SIZE>,
+    /// Number of instructions, up to 256.
+    program_size: usize,
+    registers: u8Array<16>,
+    memory: u8Array<MEMORY_SIZE>,
+    /// Can be past the 8-bit range after the last instruction, which halts.
+    pc: usize,
+    watcher: Watcher,
+    observer: O,
+}
+
+impl Machine {
+    /// A machine with the program loaded, starting at its entry point.
+    pub fn new(program: &Executable) -> Result<Self, String> {
+        Self::with_observer(program, ())
+    }
+}
+
+impl<O: Observer> Machine<O> {
+    /// A machine like `new` that calls `observer` while it runs.
+    pub fn with_observer(program: &Executable, observer: O) -> Result<Self, String> {
+        if program.code.len() > ROM_SIZE {
+            return Err(format!("Program is {} bytes but the ROM only holds {}", program.code.len(), ROM_SIZE));
+        }
+        if program.data.len() > MEMORY_SIZE {
+            return Err(format!("Data is {} bytes but memory only holds {}", program.data.len(), MEMORY_SIZE));
+        }
+
+        Ok(Self {
+            rom: u8Array::from(program.code.clone()),
+            program_size: program.code.len() / 2,
+            registers: u8Array::from([0u8; 16]),
+            memory: u8Array::from(program.data.clone()),
+            pc: program.entry as usize,
+            watcher: Watcher::default(),
+            observer,
+        })
+    }
+
+    pub fn observer(&self) -> &O {
+        &self.observer
+    }
+
+    pub fn observer_mut(&mut self) -> &mut O {
+        &mut self.observer
+    }
+
+    /// Takes the observer back, with whatever it collected.
+    pub fn into_observer(self) -> O {
+        self.observer
+    }
+
+    /// Number of instructions in the program.
+    pub fn program_size(&self) -> usize {
+        self.program_size
+    }
+
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    pub fn set_pc(&mut self, pc: u8) {
+        self.pc = pc as usize;
+    }
+
+    /// The value of register VX, `x` is 0 to 15.
+    pub fn register(&self, x: u8) -> u8 {
+        self.registers[x & 0xF]
+    }
+
+    /// Sets register VX, only the low 4 bits of `x` are used.
+    pub fn set_register(&mut self, x: u8, value: u8) {
+        self.registers[x & 0xF] = value;
+    }
+
+    pub fn memory(&self, address: u8) -> u8 {
+        self.memory[address]
+    }
+
+    pub fn set_memory(&mut self, address: u8, value: u8) {
+        self.memory[address] = value;
+    }
+
+    /// Runs the instruction at the PC.
+    pub fn step(&mut self) -> Result<Step, String> {
+        if self.pc >= self.program_size {
+            self.observer.on_halt(self.pc);
+            return Ok(Step::Halted);
+        }
+
+        let pc = self.pc;
+        let instruction = [self.rom[pc * 2], self.rom[pc * 2 + 1]];
+        self.observer.on_fetch(pc, instruction);
+        let output = self.execute(instruction)?;
+        self.observer.on_execute(pc, instruction, self.pc);
+
+        match output {
+            Some(value) => {
+                self.observer.on_output(value);
+                Ok(Step::Output(value))
+            },
+            None => Ok(Step::Running),
+        }
+    }
+
+    /// Runs `iu8` and moves the PC on. Returns the value written to the
+    /// output address, if any.
+    fn execute(&mut self, iu8: [u8; 2]) -> Result<Option<u8>, String> {
+        let mut output = None;
 
-        let iu8 = [rom[pc * 2], rom[pc * 2 + 1]];
         let iu4 = [(iu8[0] & 0xF0) >> 4, iu8[0] & 0x0F, (iu8[1] & 0xF0) >> 4, iu8[1] & 0x0F];
 
-        
+
 /*         println!("Instruction: {:02X} {:02X}", iu8[0], iu8[1]);
-        println!("PC: {}", pc);
-        println!("Registers: {:?}", registers); */
+        println!("PC: {}", self.pc);
+        println!("Registers: {:?}", self.registers); */
 
         match iu4[0] {
             0x0 => { // 00NN jump NN
                 let address = iu8[1];
-                pc = address;
-                continue;
+                self.pc = address as usize;
+                return Ok(None);
             },
             0x1 => { // 10XY ifeq VX VY
                 let vx = iu4[2];
                 let vy = iu4[3];
-                if registers[vx] == registers[vy] {
-                    pc += 1;
+                if self.read_register(vx) == self.read_register(vy) {
+                    self.pc += 1;
                 }
             },
             0x2 => { // 20XY ifneq VX VY
                 let vx = iu4[2];
                 let vy = iu4[3];
-                if registers[vx] != registers[vy] {
-                    pc += 1;
+                if self.read_register(vx) != self.read_register(vy) {
+                    self.pc += 1;
                 }
             },
             0x3 => { // 30XY ifle VX VY
                 let vx = iu4[2];
                 let vy = iu4[3];
-                if registers[vx] <= registers[vy] {
-                    pc += 1;
+                if self.read_register(vx) <= self.read_register(vy) {
+                    self.pc += 1;
                 }
             },
             0x4 => {
@@ -107,59 +335,60 @@ pub fn run(path: &str) {
                     0x0 => { // 40XY setrr VX VY
                         let vx = iu4[2];
                         let vy = iu4[3];
-                        registers[vx] = registers[vy];
+                        let value = self.read_register(vy);
+                        self.write_register(vx, value);
                     },
                     0x1 => { // 410X setrpc VX
                         let vx = iu4[3];
-                        pc = registers[vx];
+                        self.pc = self.read_register(vx) as usize;
                     },
                     _ => {
-                        print_instruction(&iu8, &iu4);
-                        panic!("Unknown instruction")
+                        return Err(self.unknown(iu8))
                     },
                 }
             },
             0x5 => { // 5XNN setrm VX NN
-                let vx = iu4[2];
+                let vx = iu4[1];
                 let nn = iu8[1];
-                registers[vx] = memory[nn];
+                let value = self.read_memory(nn);
+                self.write_register(vx, value);
             },
             0x6 => { // 6XAA setrc VX AA
                 let vx = iu4[1];
                 let aa = iu8[1];
-                registers[vx] = aa;
+                self.write_register(vx, aa);
             },
             0x7 => { // 700X setpcr VX
                 let vx = iu4[3];
-                pc = registers[vx];
+                self.pc = self.read_register(vx) as usize;
             },
             0x8 => { // 8XNN setmr NN VX
                 let nn = iu8[1];
                 let vx = iu4[1];
+                let value = self.read_register(vx);
+                self.write_memory(nn, value);
                 if nn == 0xFF {
-                    println!("{}", registers[vx]);
+                    output = Some(value);
                 }
-                memory[nn] = registers[vx];
             },
             0x9 => { 
                 match iu4[1] {
                     0x0 => { // 90XY add VX VY
                         let vx = iu4[2];
                         let vy = iu4[3];
-                        let result = registers[vx] as u16 + registers[vy] as u16;
-                        registers[vx] = result as u8;
-                        registers[0xF] = if result > 0xFF { 1 } else { 0 };
+                        let result = self.read_register(vx) as u16 + self.read_register(vy) as u16;
+                        self.write_register(vx, result as u8);
+                        self.write_register(0xF, if result > 0xFF { 1 } else { 0 });
                     },
                     0x1 => { // 91XY sub VX VY
                         let vx = iu4[2];
                         let vy = iu4[3];
-                        let result = registers[vx] as i16 - registers[vy] as i16;
-                        registers[vx] = result as u8;
-                        registers[0xF] = if result < 0 { 1 } else { 0 };
+                        let result = self.read_register(vx) as i16 - self.read_register(vy) as i16;
+                        self.write_register(vx, result as u8);
+                        self.write_register(0xF, if result < 0 { 1 } else { 0 });
                     },
                     _ => {
-                        print_instruction(&iu8, &iu4);
-                        panic!("Unknown instruction")
+                        return Err(self.unknown(iu8))
                     },
                 }
             },
@@ -168,43 +397,123 @@ pub fn run(path: &str) {
                     0x0 => { // A0XY and VX VY
                         let vx = iu4[2];
                         let vy = iu4[3];
-                        registers[vx] = registers[vx] & registers[vy];
+                        let value = self.read_register(vx) & self.read_register(vy);
+                        self.write_register(vx, value);
                     },
                     0x1 => { // A1XY or VX VY
                         let vx = iu4[2];
                         let vy = iu4[3];
-                        registers[vx] = registers[vx] | registers[vy];
+                        let value = self.read_register(vx) | self.read_register(vy);
+                        self.write_register(vx, value);
                     },
                     0x2 => { // A2XY xor VX VY
                         let vx = iu4[2];
                         let vy = iu4[3];
-                        registers[vx] = registers[vx] ^ registers[vy];
+                        let value = self.read_register(vx) ^ self.read_register(vy);
+                        self.write_register(vx, value);
                     },
                     0x3 => { // A3XY not VX
                         let vx = iu4[2];
-                        registers[vx] = !registers[vx];
+                        let value = !self.read_register(vx);
+                        self.write_register(vx, value);
                     },
                     _ => {
-                        print_instruction(&iu8, &iu4);
-                        panic!("Unknown instruction")
+                        return Err(self.unknown(iu8))
                     },
                 }
             },
             _ => {
-                print_instruction(&iu8, &iu4);
-                panic!("Unknown instruction")
+                return Err(self.unknown(iu8))
             },
         }
 
-        pc += 1;
+
+        self.pc += 1;
+
+        Ok(output)
+    }
+
+    /// Stops or logs accesses to `watchpoint`'s target, see `take_hits`.
+    pub fn watch(&mut self, watchpoint: Watchpoint) {
+        self.watcher.watch(watchpoint);
+    }
+
+    /// The watched accesses since the last call, in the order they were made.
+    pub fn take_hits(&mut self) -> Vec<Hit> {
+        self.watcher.take_hits()
     }
-}
 
-fn print_instruction(iu8: &[u8; 2], iu4: &[u8; 4]) {
-    println!("iu8: {:02X} {:02X}", iu8[0], iu8[1]);
-    println!("iu4: {:?}", iu4);
+    fn read_register(&mut self, x: u8) -> u8 {
+        let value = self.registers[x];
+        self.watcher.check(Target::Register(x), false, value, value, self.pc);
+        value
+    }
+
+    fn write_register(&mut self, x: u8, value: u8) {
+        self.watcher.check(Target::Register(x), true, self.registers[x], value, self.pc);
+        self.registers[x] = value;
+    }
+
+    fn read_memory(&mut self, address: u8) -> u8 {
+        let value = self.memory[address];
+        self.observer.on_mem_read(address as usize, value);
+        self.watcher.check(Target::Memory(address as u16), false, value, value, self.pc);
+        value
+    }
+
+    fn write_memory(&mut self, address: u8, value: u8) {
+        let old = self.memory[address];
+        self.observer.on_mem_write(address as usize, old, value);
+        self.watcher.check(Target::Memory(address as u16), true, old, value, self.pc);
+        self.memory[address] = value;
+    }
+
+    fn unknown(&self, instruction: [u8; 2]) -> String {
+        format!("Unknown instruction {:02X}{:02X} at PC {}", instruction[0], instruction[1], self.pc)
+    }
 }
 
+impl<O: Observer> Cpu for Machine<O> {
+    fn step(&mut self) -> Result<Step, String> {
+        Machine::step(self)
+    }
+
+    fn pc(&self) -> usize {
+        self.pc
+    }
+
+    fn register(&self, x: u8) -> u8 {
+        Machine::register(self, x)
+    }
+
+    fn memory_size(&self) -> usize {
+        MEMORY_SIZE
+    }
+
+    fn byte(&self, address: usize) -> u8 {
+        self.memory[address]
+    }
+
+    fn layout(&self) -> (usize, usize, usize) {
+        (0, 1, self.program_size)
+    }
+
+    fn disassemble(&self, pc: usize) -> Option<String> {
+        if pc >= self.program_size {
+            return None;
+        }
+        let code = [self.rom[pc * 2], self.rom[pc * 2 + 1]];
+        Some(disassembler::instruction(code).unwrap_or_else(|| format!("{:02X}{:02X}", code[0], code[1])))
+    }
+
+    fn watch(&mut self, watchpoint: Watchpoint) {
+        Machine::watch(self, watchpoint);
+    }
+
+    fn take_hits(&mut self) -> Vec<Hit> {
+        Machine::take_hits(self)
+    }
+}
 
 #[allow(non_camel_case_types)]
 #[derive(Debug)]
@@ -222,7 +531,7 @@ impl<const N: usize> Display for u8Array<N> {
         });
         write!(f, "{}", string)
     }
-    
+
 }
 
 impl<const N:usize> From<Vec<u8>> for u8Array<N> {
@@ -251,4 +560,12 @@ impl<const N: usize> IndexMut<u8> for u8Array<N> {
     fn index_mut(&mut self, index: u8) -> &mut Self::Output {
         &mut self.data[index as usize]
     }
+}
+
+impl<const N: usize> Index<usize> for u8Array<N> {
+    type Output = u8;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        &self.data[index]
+    }
 }
\ No newline at end of file
@@ -0,0 +1,299 @@
+use std::{fmt::Display, str::FromStr};
+
+use exe::Executable;
+
+/// Program formats the emulator can load.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Raw bytes, two per instruction.
+    Raw,
+    /// Whitespace separated hex words (like `fib.txt`).
+    Hex,
+    /// Intel HEX records.
+    Ihex,
+    /// Motorola S-records.
+    Srec,
+    /// Executable container with entry point, data and symbols.
+    Exe,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "raw" | "bin" => Ok(Format::Raw),
+            "hex" => Ok(Format::Hex),
+            "ihex" => Ok(Format::Ihex),
+            "srec" => Ok(Format::Srec),
+            "exe" => Ok(Format::Exe),
+            _ => Err(format!("Unknown format: {} (expected raw, hex, ihex, srec or exe)", s)),
+        }
+    }
+}
+
+impl Display for Format {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Format::Raw => write!(f, "raw"),
+            Format::Hex => write!(f, "hex"),
+            Format::Ihex => write!(f, "ihex"),
+            Format::Srec => write!(f, "srec"),
+            Format::Exe => write!(f, "exe"),
+        }
+    }
+}
+
+/// Guesses the format of a program. Executables are recognized by their magic,
+/// Intel HEX and S-records by a valid first record on a line of its own,
+/// anything that is only hex digits and whitespace is treated as hex text and
+/// everything else as a raw binary.
+pub fn detect(input: &[u8]) -> Format {
+    if Executable::is_executable(input) {
+        return Format::Exe;
+    }
+
+    let first = input
+        .iter()
+        .position(|byte| *byte == b'\n')
+        .and_then(|end| std::str::from_utf8(&input[..end]).ok())
+        .map(|line| line.strip_suffix('\r').unwrap_or(line));
+    if first.is_some_and(|line| ihex_record(line).is_ok()) {
+        return Format::Ihex;
+    }
+    if first.is_some_and(|line| srec_record(line).is_ok()) {
+        return Format::Srec;
+    }
+
+    let is_hex_text = input.iter().any(|byte| byte.is_ascii_hexdigit())
+        && input
+            .iter()
+            .all(|byte| byte.is_ascii_hexdigit() || byte.is_ascii_whitespace());
+
+    if is_hex_text { Format::Hex } else { Format::Raw }
+}
+
+/// Decodes a program for a core whose ROM holds `rom_size` bytes, like
+/// `ROM_SIZE` or `chip8::ROM_SIZE`. If `format` is `None` it is detected from
+/// the content. Formats other than `Exe` only hold code and start at PC 0.
+pub fn load(input: &[u8], format: Option<Format>, rom_size: usize) -> Result<Executable, String> {
+    let code = match format.unwrap_or_else(|| detect(input)) {
+        Format::Raw => input.to_vec(),
+        Format::Hex => parse_hex(input)?,
+        Format::Ihex => parse_ihex(as_text(input)?, rom_size)?,
+        Format::Srec => parse_srec(as_text(input)?, rom_size)?,
+        Format::Exe => return Executable::from_bytes(input),
+    };
+    Ok(Executable::new(code))
+}
+
+fn as_text(input: &[u8]) -> Result<&str, String> {
+    std::str::from_utf8(input).map_err(|_| "Program is not valid text".to_string())
+}
+
+fn parse_hex(input: &[u8]) -> Result<Vec<u8>, String> {
+    let text = as_text(input)?;
+
+    let mut bytes = Vec::new();
+
+    for (line_number, line) in text.lines().enumerate() {
+        for word in line.split_whitespace() {
+            if let Some(c) = word.chars().find(|c| !c.is_ascii_hexdigit()) {
+                return Err(format!(
+                    "line {}: '{}' in {} is not a hex digit",
+                    line_number + 1, c, word
+                ));
+            }
+            if !word.len().is_multiple_of(2) {
+                return Err(format!(
+                    "line {}: {} has an odd number of hex digits",
+                    line_number + 1, word
+                ));
+            }
+
+            word.as_bytes()
+                .chunks(2)
+                .for_each(|pair| bytes.push(hex_digit(pair[0]) << 4 | hex_digit(pair[1])));
+        }
+    }
+
+    Ok(bytes)
+}
+
+fn parse_ihex(text: &str, rom_size: usize) -> Result<Vec<u8>, String> {
+    let mut rom = Vec::new();
+    let mut base = 0;
+    let mut end = false;
+
+    for (line_number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let error = |e: String| format!("line {}: {}", line_number + 1, e);
+
+        if end {
+            return Err(error("record after end of file record".to_string()));
+        }
+
+        let bytes = ihex_record(line).map_err(error)?;
+        let address = base + ((bytes[1] as usize) << 8 | bytes[2] as usize);
+        let data = &bytes[4..bytes.len() - 1];
+
+        match bytes[3] {
+            0x00 => write(&mut rom, rom_size, address, data).map_err(error)?,
+            0x01 => end = true,
+            0x02 if data.len() == 2 => base = ((data[0] as usize) << 8 | data[1] as usize) << 4,
+            0x04 if data.len() == 2 => base = ((data[0] as usize) << 8 | data[1] as usize) << 16,
+            // start address records, programs always start at 0
+            0x03 | 0x05 => {},
+            record_type => {
+                return Err(error(format!("invalid record type {:02X}", record_type)));
+            }
+        }
+    }
+
+    if !end {
+        return Err("missing end of file record".to_string());
+    }
+
+    Ok(rom)
+}
+
+fn parse_srec(text: &str, rom_size: usize) -> Result<Vec<u8>, String> {
+    let mut rom = Vec::new();
+    let mut data_records = 0;
+    let mut end = false;
+
+    for (line_number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let error = |e: String| format!("line {}: {}", line_number + 1, e);
+
+        if end {
+            return Err(error("record after termination record".to_string()));
+        }
+
+        let (record_type, bytes) = srec_record(line).map_err(error)?;
+        let address_size = address_size(record_type);
+        let address = bytes[1..=address_size]
+            .iter()
+            .fold(0, |address, byte| address << 8 | *byte as usize);
+        let data = &bytes[address_size + 1..bytes.len() - 1];
+
+        match record_type {
+            '1' | '2' | '3' => {
+                write(&mut rom, rom_size, address, data).map_err(error)?;
+                data_records += 1;
+            },
+            '5' | '6' if address != data_records => {
+                return Err(error(format!(
+                    "record count is {} but {} data records were read",
+                    address, data_records
+                )));
+            },
+            '7' | '8' | '9' => end = true,
+            _ => {},
+        }
+    }
+
+    if !end {
+        return Err("missing termination record".to_string());
+    }
+
+    Ok(rom)
+}
+
+/// The bytes of an Intel HEX record, from the byte count to the checksum,
+/// after checking its length and checksum.
+fn ihex_record(line: &str) -> Result<Vec<u8>, String> {
+    let record = line
+        .strip_prefix(':')
+        .ok_or_else(|| "record does not start with ':'".to_string())?;
+    let bytes = record_bytes(record)?;
+
+    if bytes.len() < 5 || bytes.len() != bytes[0] as usize + 5 {
+        return Err("record length does not match its byte count".to_string());
+    }
+    if sum(&bytes) != 0 {
+        return Err(format!(
+            "checksum mismatch (expected {:02X}, found {:02X})",
+            sum(&bytes[..bytes.len() - 1]).wrapping_neg(), bytes[bytes.len() - 1]
+        ));
+    }
+    Ok(bytes)
+}
+
+/// The type and bytes of an S-record, from the byte count to the checksum,
+/// after checking its length and checksum.
+fn srec_record(line: &str) -> Result<(char, Vec<u8>), String> {
+    let record = line
+        .strip_prefix('S')
+        .ok_or_else(|| "record does not start with 'S'".to_string())?;
+    let record_type = record
+        .chars()
+        .next()
+        .ok_or_else(|| "missing record type".to_string())?;
+    if !matches!(record_type, '0'..='3' | '5'..='9') {
+        return Err(format!("invalid record type S{}", record_type));
+    }
+    let bytes = record_bytes(&record[record_type.len_utf8()..])?;
+
+    if bytes.len() < address_size(record_type) + 2 || bytes.len() != bytes[0] as usize + 1 {
+        return Err("record length does not match its byte count".to_string());
+    }
+    if sum(&bytes) != 0xFF {
+        return Err(format!(
+            "checksum mismatch (expected {:02X}, found {:02X})",
+            !sum(&bytes[..bytes.len() - 1]), bytes[bytes.len() - 1]
+        ));
+    }
+    Ok((record_type, bytes))
+}
+
+/// Bytes of the address field of a valid S-record type.
+fn address_size(record_type: char) -> usize {
+    match record_type {
+        '2' | '6' | '8' => 3,
+        '3' | '7' => 4,
+        _ => 2,
+    }
+}
+
+/// Copies a data record into the ROM image at its load address.
+fn write(rom: &mut Vec<u8>, rom_size: usize, address: usize, data: &[u8]) -> Result<(), String> {
+    let end = address + data.len();
+    if end > rom_size {
+        return Err(format!("data at 0x{:04X} does not fit in the {} byte ROM", address, rom_size));
+    }
+    if rom.len() < end {
+        rom.resize(end, 0);
+    }
+    rom[address..end].copy_from_slice(data);
+    Ok(())
+}
+
+fn record_bytes(record: &str) -> Result<Vec<u8>, String> {
+    if let Some(c) = record.chars().find(|c| !c.is_ascii_hexdigit()) {
+        return Err(format!("'{}' is not a hex digit", c));
+    }
+    if !record.len().is_multiple_of(2) {
+        return Err("record has an odd number of hex digits".to_string());
+    }
+
+    Ok(record
+        .as_bytes()
+        .chunks(2)
+        .map(|pair| hex_digit(pair[0]) << 4 | hex_digit(pair[1]))
+        .collect())
+}
+
+fn sum(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+}
+
+fn hex_digit(c: u8) -> u8 {
+    (c as char).to_digit(16).expect("not hex") as u8
+}
@@ -1,5 +1,115 @@
-use std::env::args;
+use std::{env::args, process::ExitCode};
 
-fn main() {
-    emulator::run(&args().nth(1).expect("No file path provided"));
-}
\ No newline at end of file
+use emulator::{watch::{Action, Watchpoint}, Options};
+
+const USAGE: &str = "\
+Usage: emulator [options] <file>
+
+Options:
+    --format <format>  Program format: raw, hex, ihex, srec or exe (default: detected)
+    --profile          Print how often each instruction ran to stderr
+    --folded <file>    Write the profile as folded stacks for flamegraphs to <file>
+    --coverage <file>  Write an lcov coverage report to <file> (needs assembler -g)
+    --watch <spec>     Stop when a register or memory address is accessed, like VF=1,
+                       [0x20] (changed), V3:read or V3:write (can be repeated)
+    --log <spec>       Print accesses like --watch but keep running (can be repeated)
+    --tui              Step through the program in a full-screen terminal UI
+    --chip8            Run a CHIP-8 ROM, shown in the terminal (keys 1-4 q-r a-f z-v, Esc quits),
+                       works with --tui, --watch and --log
+    --cycles <n>       With --chip8, run at most <n> instructions without the terminal
+                       and print the display
+    --snapshot <file>  With --chip8, write the display to <file> as a PBM image at the end
+    -h, --help         Print this help";
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Option<(String, Options)>, String> {
+    let mut path = None;
+    let mut options = Options::default();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--format" => {
+                options.format = Some(args.next().ok_or("--format expects a format")?.parse()?);
+            },
+            "--profile" => {
+                options.profile = true;
+            },
+            "--folded" => {
+                options.folded = Some(args.next().ok_or("--folded expects a file")?);
+            },
+            "--coverage" => {
+                options.coverage = Some(args.next().ok_or("--coverage expects a file")?);
+            },
+            "--tui" => {
+                options.tui = true;
+            },
+            "--chip8" => {
+                options.chip8 = true;
+            },
+            "--cycles" => {
+                let cycles = args.next().ok_or("--cycles expects a number")?;
+                options.cycles = Some(cycles.parse().map_err(|_| format!("Invalid number for --cycles: {}", cycles))?);
+            },
+            "--snapshot" => {
+                options.snapshot = Some(args.next().ok_or("--snapshot expects a file")?);
+            },
+            "--watch" | "--log" => {
+                let mut watchpoint: Watchpoint = args.next().ok_or(format!("{} expects a watchpoint", arg))?.parse()?;
+                if arg == "--log" {
+                    watchpoint.action = Action::Log;
+                }
+                options.watchpoints.push(watchpoint);
+            },
+            "-h" | "--help" => {
+                return Ok(None);
+            },
+            _ if arg.starts_with('-') => {
+                return Err(format!("Unknown option: {}", arg));
+            },
+            _ if path.is_none() => {
+                path = Some(arg);
+            },
+            _ => {
+                return Err(format!("Unexpected argument: {}", arg));
+            }
+        }
+    }
+
+    let profiling = options.profile || options.folded.is_some() || options.coverage.is_some();
+    if options.chip8 && profiling {
+        return Err("--chip8 can not be used with --profile, --folded or --coverage".to_string());
+    }
+    if !options.chip8 && (options.cycles.is_some() || options.snapshot.is_some()) {
+        return Err("--cycles and --snapshot need --chip8".to_string());
+    }
+    if options.tui && options.cycles.is_some() {
+        return Err("--cycles can not be used with --tui".to_string());
+    }
+    if options.tui && profiling {
+        return Err("--tui can not be used with --profile, --folded or --coverage".to_string());
+    }
+
+    Ok(Some((path.ok_or("No file path provided")?, options)))
+}
+
+fn main() -> ExitCode {
+    let (path, options) = match parse_args(args().skip(1)) {
+        Ok(Some(args)) => args,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        },
+        Err(e) => {
+            eprintln!("error: {}", e);
+            eprintln!("{}", USAGE);
+            return ExitCode::from(2);
+        }
+    };
+
+    match emulator::run(&path, &options) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}: {}", path, e);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,136 @@
+/*
+Callbacks from the execution loop, for tools built on the emulator like
+tracers, profilers and coverage.
+
+The machine is generic over its observer and every callback does nothing by
+default, so `Machine` without an observer (`()`) compiles to the same loop as
+before. `Option<T>` observes only when it is `Some`, and a pair observes with
+both, so several tools can run at once:
+
+    let observer = (Some(Profile::new()), None::<Coverage>);
+    let mut machine = Machine::with_observer(&program, observer)?;
+*/
+
+/// Called by `Machine::step` and `Chip8::step`. The accesses of an
+/// instruction are reported between its `on_fetch` and `on_execute`.
+#[allow(unused_variables)]
+pub trait Observer {
+    /// The instruction at `pc` is about to run.
+    fn on_fetch(&mut self, pc: usize, instruction: [u8; 2]) {}
+
+    /// The instruction at `pc` ran and the PC moved on to `next`. Not called
+    /// for unknown instructions.
+    fn on_execute(&mut self, pc: usize, instruction: [u8; 2], next: usize) {}
+
+    /// `value` was read from `memory[address]`.
+    fn on_mem_read(&mut self, address: usize, value: u8) {}
+
+    /// `value` was written to `memory[address]` over `old`.
+    fn on_mem_write(&mut self, address: usize, old: u8, value: u8) {}
+
+    /// `value` was written to the output address 0xFF. CHIP-8 has no output
+    /// address.
+    fn on_output(&mut self, value: u8) {}
+
+    /// The PC is past the end of the program, or at a CHIP-8 jump to itself.
+    /// Called on every step after the machine halted.
+    fn on_halt(&mut self, pc: usize) {}
+}
+
+impl Observer for () {}
+
+impl<T: Observer + ?Sized> Observer for &mut T {
+    fn on_fetch(&mut self, pc: usize, instruction: [u8; 2]) {
+        (**self).on_fetch(pc, instruction);
+    }
+
+    fn on_execute(&mut self, pc: usize, instruction: [u8; 2], next: usize) {
+        (**self).on_execute(pc, instruction, next);
+    }
+
+    fn on_mem_read(&mut self, address: usize, value: u8) {
+        (**self).on_mem_read(address, value);
+    }
+
+    fn on_mem_write(&mut self, address: usize, old: u8, value: u8) {
+        (**self).on_mem_write(address, old, value);
+    }
+
+    fn on_output(&mut self, value: u8) {
+        (**self).on_output(value);
+    }
+
+    fn on_halt(&mut self, pc: usize) {
+        (**self).on_halt(pc);
+    }
+}
+
+impl<T: Observer> Observer for Option<T> {
+    fn on_fetch(&mut self, pc: usize, instruction: [u8; 2]) {
+        if let Some(observer) = self {
+            observer.on_fetch(pc, instruction);
+        }
+    }
+
+    fn on_execute(&mut self, pc: usize, instruction: [u8; 2], next: usize) {
+        if let Some(observer) = self {
+            observer.on_execute(pc, instruction, next);
+        }
+    }
+
+    fn on_mem_read(&mut self, address: usize, value: u8) {
+        if let Some(observer) = self {
+            observer.on_mem_read(address, value);
+        }
+    }
+
+    fn on_mem_write(&mut self, address: usize, old: u8, value: u8) {
+        if let Some(observer) = self {
+            observer.on_mem_write(address, old, value);
+        }
+    }
+
+    fn on_output(&mut self, value: u8) {
+        if let Some(observer) = self {
+            observer.on_output(value);
+        }
+    }
+
+    fn on_halt(&mut self, pc: usize) {
+        if let Some(observer) = self {
+            observer.on_halt(pc);
+        }
+    }
+}
+
+impl<A: Observer, B: Observer> Observer for (A, B) {
+    fn on_fetch(&mut self, pc: usize, instruction: [u8; 2]) {
+        self.0.on_fetch(pc, instruction);
+        self.1.on_fetch(pc, instruction);
+    }
+
+    fn on_execute(&mut self, pc: usize, instruction: [u8; 2], next: usize) {
+        self.0.on_execute(pc, instruction, next);
+        self.1.on_execute(pc, instruction, next);
+    }
+
+    fn on_mem_read(&mut self, address: usize, value: u8) {
+        self.0.on_mem_read(address, value);
+        self.1.on_mem_read(address, value);
+    }
+
+    fn on_mem_write(&mut self, address: usize, old: u8, value: u8) {
+        self.0.on_mem_write(address, old, value);
+        self.1.on_mem_write(address, old, value);
+    }
+
+    fn on_output(&mut self, value: u8) {
+        self.0.on_output(value);
+        self.1.on_output(value);
+    }
+
+    fn on_halt(&mut self, pc: usize) {
+        self.0.on_halt(pc);
+        self.1.on_halt(pc);
+    }
+}
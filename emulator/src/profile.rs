@@ -0,0 +1,158 @@
+/*
+Counts how often every instruction runs, for `emulator --profile`.
+
+Every executed PC is counted, and so is every jump back to an earlier or the
+same PC, which is how loops look in this ISA. With debug info from
+`assembler -g` the report names the source line and label of each
+instruction.
+
+The ISA has no call instruction, so the folded stacks for flamegraphs use the
+label an instruction is under as its frame: `fib.asm;loop 120`.
+*/
+
+use std::collections::HashMap;
+
+use exe::Executable;
+
+use crate::{disassembler, observer::Observer};
+
+/// Opcode classes by the first nibble of the instruction.
+const CLASSES: [&str; 11] = ["Flow", "Cond", "Cond", "Cond", "Reg", "Reg", "Reg", "PC", "Mem", "Math", "BitOp"];
+
+/// Number of loops shown in the report.
+const HOT_LOOPS: usize = 10;
+
+#[derive(Debug, Clone, Default)]
+pub struct Profile {
+    /// Executions per PC.
+    counts: Vec<u64>,
+    /// Backward jumps as (from PC, to PC) and how often they were taken.
+    loops: HashMap<(usize, usize), u64>,
+}
+
+impl Profile {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of instructions that ran.
+    pub fn total(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// Number of times the instruction at `pc` ran.
+    pub fn count(&self, pc: usize) -> u64 {
+        self.counts.get(pc).copied().unwrap_or(0)
+    }
+
+    /// Number of times the jump from `from` back to `to` was taken.
+    pub fn iterations(&self, from: usize, to: usize) -> u64 {
+        self.loops.get(&(from, to)).copied().unwrap_or(0)
+    }
+
+    /// The report for `emulator --profile`: instructions by count, opcode
+    /// classes and the hottest loops.
+    pub fn report(&self, program: &Executable) -> String {
+        let total = self.total();
+        let percent = |count: u64| count as f64 * 100.0 / total.max(1) as f64;
+        let debug = program.debug.as_ref();
+
+        let mut report = format!("profile: {} instructions\n\n", total);
+
+        let mut executed: Vec<(usize, u64)> = self.counts.iter().copied().enumerate().filter(|(_, count)| *count > 0).collect();
+        executed.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        report.push_str("   count       %   PC  instruction        location\n");
+        for (pc, count) in &executed {
+            let mut location = debug.and_then(|debug| debug.location(*pc)).unwrap_or_default();
+            if let Some(label) = debug.and_then(|debug| debug.label(*pc)) {
+                location.push_str(&format!(" ({})", label));
+            }
+            report.push_str(&format!(
+                "{:>8} {:>6.1}% {:>4}  {:<18} {}\n",
+                count, percent(*count), pc, instruction(program, *pc), location.trim_start()
+            ));
+        }
+
+        let mut classes: Vec<(&str, u64)> = Vec::new();
+        for (pc, count) in &executed {
+            let class = code(program, *pc).map_or("?", |code| CLASSES.get((code[0] >> 4) as usize).copied().unwrap_or("?"));
+            match classes.iter_mut().find(|(name, _)| *name == class) {
+                Some((_, sum)) => *sum += count,
+                None => classes.push((class, *count)),
+            }
+        }
+        classes.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        report.push_str("\nby opcode class:\n");
+        for (class, count) in &classes {
+            report.push_str(&format!("{:>8} {:>6.1}%  {}\n", count, percent(*count), class));
+        }
+
+        let mut loops: Vec<(&(usize, usize), &u64)> = self.loops.iter().collect();
+        loops.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        if !loops.is_empty() {
+            report.push_str("\nhot loops:\n");
+        }
+        for ((from, to), iterations) in loops.into_iter().take(HOT_LOOPS) {
+            let body: u64 = self.counts[*to..=*from].iter().sum();
+            let mut line = format!("{:>8} iterations  PC {}..={} ({:.1}% of instructions)", iterations, to, from, percent(body));
+            if let Some(debug) = debug {
+                let start = debug.location(*to).unwrap_or_default();
+                let end = debug.location(*from).unwrap_or_default();
+                line.push_str(&format!("  {} to {}", start, end));
+                if let Some(label) = debug.label(*to) {
+                    line.push_str(&format!(" ({})", label));
+                }
+            }
+            report.push_str(&line);
+            report.push('\n');
+        }
+
+        report
+    }
+
+    /// Folded stacks for flamegraph tools, one line per label with the
+    /// instructions that ran under it. Without debug info every PC is its own
+    /// frame.
+    pub fn folded(&self, program: &Executable) -> String {
+        let debug = program.debug.as_ref();
+        let root = debug.and_then(|debug| debug.source.as_deref()).unwrap_or("program");
+
+        let mut frames: Vec<(String, u64)> = Vec::new();
+        for (pc, count) in self.counts.iter().enumerate().filter(|(_, count)| **count > 0) {
+            let frame = match debug.and_then(|debug| debug.label(pc)) {
+                Some(label) => label.to_string(),
+                None => format!("PC {}", pc),
+            };
+            match frames.iter_mut().find(|(name, _)| *name == frame) {
+                Some((_, sum)) => *sum += count,
+                None => frames.push((frame, *count)),
+            }
+        }
+
+        frames.iter().map(|(frame, count)| format!("{};{} {}\n", root, frame, count)).collect()
+    }
+}
+
+impl Observer for Profile {
+    fn on_execute(&mut self, pc: usize, _: [u8; 2], next: usize) {
+        if self.counts.len() <= pc {
+            self.counts.resize(pc + 1, 0);
+        }
+        self.counts[pc] += 1;
+        if next <= pc {
+            *self.loops.entry((pc, next)).or_default() += 1;
+        }
+    }
+}
+
+fn code(program: &Executable, pc: usize) -> Option<[u8; 2]> {
+    let code = program.code.get(pc * 2..pc * 2 + 2)?;
+    Some([code[0], code[1]])
+}
+
+fn instruction(program: &Executable, pc: usize) -> String {
+    match code(program, pc) {
+        Some(code) => disassembler::instruction(code).unwrap_or_else(|| format!("{:02X}{:02X}", code[0], code[1])),
+        None => "?".to_string(),
+    }
+}
@@ -0,0 +1,427 @@
+/*
+Full-screen terminal front-end for `emulator --tui`, for any `Cpu`.
+
+The screen shows the disassembly around the PC, the registers, a hex dump of
+256 bytes of memory and the values written to 0xFF. Registers and memory
+bytes that the last step or run changed are highlighted. With `--chip8` it
+also shows I, the timers and the display, the hex dump follows I and the
+keypad keys are typed as their hex digit, `0`-`9` and `A`-`F`.
+
+There are no dependencies, so the terminal is driven with ANSI escape codes
+and put into raw mode with `stty`, which needs a Unix terminal.
+
+| Key | Action |
+|-|-|
+| `s`, space | Step one instruction |
+| `r` | Run until a breakpoint, a watchpoint, an error or the end |
+| `p` | Pause a run |
+| `b` | Toggle a breakpoint at the selected line |
+| `j`, `k`, arrows | Select a line |
+| `.` | Select the line at the PC |
+| `q` | Quit |
+*/
+
+use std::{
+    io::{self, Read, Write},
+    process::{Command, Stdio},
+};
+
+use exe::Executable;
+
+use crate::{cpu::Cpu, watch::Action, Step};
+
+/// Instructions run between two screen updates while running.
+const STEPS_PER_FRAME: usize = 1000;
+/// Width of the disassembly column.
+const LEFT_WIDTH: usize = 26;
+/// Values kept in the output pane.
+const OUTPUT_SIZE: usize = 64;
+/// Rows of 16 bytes in the hex dump.
+const MEMORY_ROWS: usize = 16;
+
+const RESET: &str = "\x1b[0m";
+const REVERSE: &str = "\x1b[7m";
+const CHANGED: &str = "\x1b[1;33m";
+const BREAKPOINT: &str = "\x1b[31m";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Paused,
+    Running,
+    /// Halted or stopped by an error, only quitting is left.
+    Done,
+}
+
+struct Tui<'a, C: Cpu> {
+    program: &'a Executable,
+    cpu: &'a mut C,
+    state: State,
+    /// Breakpoints by line of the disassembly, one line per instruction.
+    breakpoints: Vec<bool>,
+    /// Selected line in the disassembly.
+    cursor: usize,
+    /// First line shown in the disassembly.
+    top: usize,
+    /// Registers and memory before the last step or run.
+    registers: [u8; 16],
+    memory: Vec<u8>,
+    output: Vec<u8>,
+    cycles: usize,
+    message: String,
+}
+
+/// Puts the terminal into raw mode on the alternate screen and restores it
+/// when dropped, also when the TUI returns with an error.
+pub(crate) struct Terminal {
+    settings: String,
+}
+
+impl Terminal {
+    /// Reads from stdin return after `timeout` tenths of a second without
+    /// input, or right away for 0.
+    pub(crate) fn new(timeout: u8) -> Result<Self, String> {
+        let settings = stty(&["-g"]).map_err(|_| "The emulator needs a terminal for this".to_string())?;
+        stty(&["-icanon", "-echo", "min", "0", "time", &timeout.to_string()])?;
+        print!("\x1b[?1049h\x1b[?25l");
+        Ok(Self { settings: settings.trim().to_string() })
+    }
+
+    /// Rows and columns of the terminal.
+    fn size() -> (usize, usize) {
+        let size = stty(&["size"]).unwrap_or_default();
+        let mut numbers = size.split_whitespace().filter_map(|number| number.parse().ok());
+        (numbers.next().unwrap_or(24), numbers.next().unwrap_or(80))
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        print!("\x1b[?25h\x1b[?1049l");
+        let _ = io::stdout().flush();
+        let _ = stty(&[&self.settings]);
+    }
+}
+
+/// Runs `stty` on the terminal of stdin and returns what it printed.
+fn stty(args: &[&str]) -> Result<String, String> {
+    let output = Command::new("stty")
+        .args(args)
+        .stdin(Stdio::inherit())
+        .stderr(Stdio::null())
+        .output()
+        .map_err(|e| format!("stty: {}", e))?;
+    if !output.status.success() {
+        return Err("stty failed".to_string());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Runs `program`, loaded into `cpu`, in the TUI until the user quits.
+/// Watchpoints that stop pause a run, logged ones are shown in the status
+/// line.
+pub fn run(program: &Executable, cpu: &mut impl Cpu) -> Result<(), String> {
+    let mut tui = Tui::new(program, cpu);
+
+    // reads wait for 0.1s, so a run can be paused
+    let _terminal = Terminal::new(1)?;
+    let mut stdin = io::stdin();
+    let mut input = [0; 16];
+    let mut dirty = true;
+    loop {
+        if tui.state == State::Running {
+            tui.run(STEPS_PER_FRAME);
+            dirty = true;
+        }
+        if dirty {
+            tui.draw()?;
+            dirty = false;
+        }
+
+        let count = stdin.read(&mut input).map_err(|e| e.to_string())?;
+        let mut keys = &input[..count];
+        while !keys.is_empty() {
+            // arrow keys arrive as ESC [ A and ESC [ B
+            let (key, rest) = match keys {
+                [0x1b, b'[', b'A', rest @ ..] => (b'k', rest),
+                [0x1b, b'[', b'B', rest @ ..] => (b'j', rest),
+                [key, rest @ ..] => (*key, rest),
+                [] => break,
+            };
+            keys = rest;
+            if key == b'q' {
+                return Ok(());
+            }
+            tui.key(key);
+            dirty = true;
+        }
+    }
+}
+
+/// The first screen of the TUI for `program`, loaded into `cpu`, on a
+/// terminal of `rows` and `columns`, without touching the terminal.
+pub fn frame(program: &Executable, cpu: &mut impl Cpu, rows: usize, columns: usize) -> String {
+    let mut tui = Tui::new(program, cpu);
+    tui.scroll(rows);
+    tui.frame(rows, columns)
+}
+
+impl<'a, C: Cpu> Tui<'a, C> {
+    /// A paused TUI with the line at the PC selected.
+    fn new(program: &'a Executable, cpu: &'a mut C) -> Self {
+        let (_, _, lines) = cpu.layout();
+        let mut tui = Tui {
+            program,
+            state: State::Paused,
+            breakpoints: vec![false; lines],
+            cursor: 0,
+            top: 0,
+            registers: [0; 16],
+            memory: vec![0; cpu.memory_size()],
+            output: Vec::new(),
+            cycles: 0,
+            message: "s: step  r: run  p: pause  b: breakpoint  j/k: select  q: quit".to_string(),
+            cpu,
+        };
+        tui.snapshot();
+        tui.follow();
+        tui
+    }
+
+    fn key(&mut self, key: u8) {
+        match key {
+            b's' | b' ' if self.state != State::Done => {
+                self.snapshot();
+                self.state = State::Paused;
+                self.step();
+                self.follow();
+            },
+            b'r' if self.state == State::Paused => {
+                self.snapshot();
+                self.state = State::Running;
+                self.message = "running, p to pause".to_string();
+                // the first step leaves a breakpoint at the PC
+                self.step();
+                self.follow();
+            },
+            b'p' if self.state == State::Running => {
+                self.state = State::Paused;
+                self.message = format!("paused at PC {}", self.cpu.pc());
+                self.follow();
+            },
+            b'b' if !self.breakpoints.is_empty() => {
+                let breakpoint = &mut self.breakpoints[self.cursor];
+                *breakpoint = !*breakpoint;
+            },
+            b'j' => {
+                self.cursor = (self.cursor + 1).min(self.breakpoints.len().saturating_sub(1));
+            },
+            b'k' => {
+                self.cursor = self.cursor.saturating_sub(1);
+            },
+            b'.' => {
+                self.follow();
+            },
+            b'0'..=b'9' | b'A'..=b'F' => {
+                self.cpu.press((key as char).to_digit(16).unwrap_or(0) as u8);
+            },
+            _ => {},
+        }
+    }
+
+    /// Runs up to `steps` instructions, pausing at breakpoints.
+    fn run(&mut self, steps: usize) {
+        for _ in 0..steps {
+            if self.state != State::Running {
+                break;
+            }
+            let pc = self.cpu.pc();
+            if self.line(pc).is_some_and(|line| self.breakpoints[line]) {
+                self.state = State::Paused;
+                self.message = format!("breakpoint at PC {}", pc);
+                break;
+            }
+            self.step();
+        }
+        self.follow();
+    }
+
+    /// Runs one instruction. Halting, errors and watchpoints that stop end a
+    /// run.
+    fn step(&mut self) {
+        match self.cpu.step() {
+            Ok(Step::Running) => {},
+            Ok(Step::Output(value)) => {
+                self.output.push(value);
+                if self.output.len() > OUTPUT_SIZE {
+                    self.output.remove(0);
+                }
+            },
+            Ok(Step::Halted) => {
+                self.state = State::Done;
+                self.message = "halted, q to quit".to_string();
+                return;
+            },
+            Err(e) => {
+                self.state = State::Done;
+                self.message = format!("error: {}", e);
+                return;
+            },
+        }
+        self.cycles += 1;
+        if self.cpu.steps_per_frame().is_some_and(|steps| self.cycles.is_multiple_of(steps)) {
+            self.cpu.tick();
+        }
+
+        for hit in self.cpu.take_hits() {
+            self.message = format!("watchpoint: {}", hit);
+            if hit.watchpoint.action == Action::Stop {
+                self.state = State::Paused;
+            }
+        }
+    }
+
+    fn snapshot(&mut self) {
+        for x in 0..16 {
+            self.registers[x] = self.cpu.register(x as u8);
+        }
+        for address in 0..self.memory.len() {
+            self.memory[address] = self.cpu.byte(address);
+        }
+    }
+
+    /// The line of the disassembly with the instruction at `pc`, if there is
+    /// one.
+    fn line(&self, pc: usize) -> Option<usize> {
+        let (start, stride, lines) = self.cpu.layout();
+        let offset = pc.checked_sub(start)?;
+        (offset % stride == 0 && offset / stride < lines).then_some(offset / stride)
+    }
+
+    /// The PC of the instruction on `line` of the disassembly.
+    fn pc(&self, line: usize) -> usize {
+        let (start, stride, _) = self.cpu.layout();
+        start + line * stride
+    }
+
+    /// Selects the line at the PC, or the nearest one if it is past the
+    /// program.
+    fn follow(&mut self) {
+        let (start, stride, lines) = self.cpu.layout();
+        let line = self.cpu.pc().saturating_sub(start) / stride;
+        self.cursor = line.min(lines.saturating_sub(1));
+    }
+
+    fn draw(&mut self) -> Result<(), String> {
+        let (rows, columns) = Terminal::size();
+        self.scroll(rows);
+        let screen = self.frame(rows, columns);
+        let mut stdout = io::stdout();
+        stdout.write_all(screen.as_bytes()).and_then(|_| stdout.flush()).map_err(|e| e.to_string())
+    }
+
+    /// Scrolls the disassembly so the selected line is on a screen of `rows`.
+    fn scroll(&mut self, rows: usize) {
+        let height = height(rows);
+        if self.cursor < self.top {
+            self.top = self.cursor;
+        }
+        if self.cursor >= self.top + height {
+            self.top = self.cursor + 1 - height;
+        }
+    }
+
+    /// The whole screen as ANSI escape codes, with the header, the
+    /// disassembly next to the state, the output and the status line.
+    fn frame(&self, rows: usize, columns: usize) -> String {
+        let height = height(rows);
+        let pc = self.cpu.pc();
+        let mut header = format!("PC {}  cycles {}", pc, self.cycles);
+        if let Some(debug) = &self.program.debug {
+            if let Some(location) = debug.location(pc) {
+                header.push_str(&format!("  {}", location));
+            }
+            if let Some(label) = debug.label(pc) {
+                header.push_str(&format!(" ({})", label));
+            }
+        }
+        let state = match self.state {
+            State::Paused => "paused",
+            State::Running => "running",
+            State::Done => "done",
+        };
+        header = format!("{:<width$}{}", header, state, width = columns.saturating_sub(state.len() + 1));
+
+        let left = (self.top..self.top + height).map(|line| self.disassembly(line));
+        let right = self.state_lines().into_iter().chain(std::iter::repeat(String::new()));
+
+        let mut screen = format!("\x1b[H{}{}{}\x1b[K\r\n", REVERSE, header, RESET);
+        for (left, right) in left.zip(right) {
+            screen.push_str(&format!("{} | {}\x1b[K\r\n", left, right));
+        }
+
+        let output: Vec<String> = self.output.iter().map(u8::to_string).collect();
+        let mut output = format!("output: {}", output.join(" "));
+        if output.len() > columns {
+            output = format!("output: ...{}", &output[output.len() + 11 - columns..]);
+        }
+        screen.push_str(&format!("{}\x1b[K\r\n", output));
+        screen.push_str(&format!("{}\x1b[K\x1b[J", truncate(&self.message, columns)));
+        screen
+    }
+
+    /// One line of the disassembly, padded to the column width.
+    fn disassembly(&self, line: usize) -> String {
+        let pc = self.pc(line);
+        let (Some(text), Some(breakpoint)) = (self.cpu.disassemble(pc), self.breakpoints.get(line)) else {
+            return " ".repeat(LEFT_WIDTH);
+        };
+        let cursor = if line == self.cursor { '>' } else { ' ' };
+        let text = truncate(&format!("{:>3}  {}", pc, text), LEFT_WIDTH - 2);
+        let text = format!("{:<width$}", text, width = LEFT_WIDTH - 2);
+        let text = if pc == self.cpu.pc() { format!("{}{}{}", REVERSE, text, RESET) } else { text };
+
+        if *breakpoint {
+            format!("{}{}*{}{}", cursor, BREAKPOINT, RESET, text)
+        }
+        else {
+            format!("{} {}", cursor, text)
+        }
+    }
+
+    /// The register, core and memory lines of the right column.
+    fn state_lines(&self) -> Vec<String> {
+        let byte = |value: u8, old: u8| {
+            if value == old { format!("{:02X}", value) } else { format!("{}{:02X}{}", CHANGED, value, RESET) }
+        };
+
+        let mut lines = vec!["registers".to_string()];
+        for row in 0..2 {
+            let registers: Vec<String> = (row * 8..row * 8 + 8)
+                .map(|x| format!("V{:X} {}", x, byte(self.cpu.register(x as u8), self.registers[x])))
+                .collect();
+            lines.push(registers.join(" "));
+        }
+        lines.extend(self.cpu.details());
+
+        lines.push("memory".to_string());
+        let start = self.cpu.memory_view();
+        let rows = (self.memory.len() - start).div_ceil(16).min(MEMORY_ROWS);
+        for row in start / 16..start / 16 + rows {
+            let bytes: Vec<String> = (row * 16..(row * 16 + 16).min(self.memory.len()))
+                .map(|address| byte(self.cpu.byte(address), self.memory[address]))
+                .collect();
+            lines.push(format!("{:02X}: {}", row * 16, bytes.join(" ")));
+        }
+        lines
+    }
+}
+
+/// Lines of disassembly on a screen of `rows`, without the header, output and
+/// status lines.
+fn height(rows: usize) -> usize {
+    rows.saturating_sub(4).max(1)
+}
+
+fn truncate(text: &str, width: usize) -> String {
+    text.chars().take(width).collect()
+}
@@ -0,0 +1,174 @@
+/*
+Watchpoints on registers and memory. `Machine` and `Chip8` check them on
+every access an instruction makes, and every match is a `Hit` the caller can
+log or stop at. Accesses through `Machine::set_register` and the other
+setters from outside are not watched.
+
+On the command line a watchpoint is written as its target and what to watch
+for:
+
+| Spec | Matches |
+|-|-|
+| `V3`, `[0x20]` | A write that changes the value |
+| `V3:read` | A read |
+| `V3:write` | Any write, even of the same value |
+| `VF=1` | A write of the value 1 |
+
+Memory addresses go up to 0xFFF for the 4 KB of CHIP-8.
+*/
+
+use std::{fmt::Display, str::FromStr};
+
+/// What a watchpoint watches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    Register(u8),
+    Memory(u16),
+}
+
+/// The accesses a watchpoint matches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+    /// A write of a different value than before.
+    Change,
+    /// A write of this value.
+    Value(u8),
+}
+
+/// What to do when a watchpoint matches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// Stop after the instruction.
+    Stop,
+    /// Report the access and keep running.
+    Log,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub target: Target,
+    pub access: Access,
+    pub action: Action,
+}
+
+/// An access that matched a watchpoint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Hit {
+    pub watchpoint: Watchpoint,
+    /// PC of the instruction that made the access.
+    pub pc: usize,
+    /// The value before the access.
+    pub old: u8,
+    /// The value read or written.
+    pub value: u8,
+}
+
+/// The watchpoints of a core and the hits since they were last taken.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Watcher {
+    watchpoints: Vec<Watchpoint>,
+    hits: Vec<Hit>,
+}
+
+impl Watcher {
+    pub fn watch(&mut self, watchpoint: Watchpoint) {
+        self.watchpoints.push(watchpoint);
+    }
+
+    pub fn take_hits(&mut self) -> Vec<Hit> {
+        std::mem::take(&mut self.hits)
+    }
+
+    /// Records a hit for every watchpoint on `target` that reading `value`
+    /// or writing it over `old` at `pc` matches.
+    pub fn check(&mut self, target: Target, write: bool, old: u8, value: u8, pc: usize) {
+        for watchpoint in &self.watchpoints {
+            if watchpoint.target == target && watchpoint.matches(write, old, value) {
+                self.hits.push(Hit { watchpoint: *watchpoint, pc, old, value });
+            }
+        }
+    }
+}
+
+impl Watchpoint {
+    /// Whether reading `value` or writing it over `old` matches.
+    fn matches(&self, write: bool, old: u8, value: u8) -> bool {
+        match self.access {
+            Access::Read => !write,
+            Access::Write => write,
+            Access::Change => write && old != value,
+            Access::Value(expected) => write && value == expected,
+        }
+    }
+}
+
+impl FromStr for Watchpoint {
+    type Err = String;
+
+    /// Parses a spec like `VF=1` or `[0x20]:read` into a watchpoint that
+    /// stops.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (target, access) = match (s.split_once('='), s.split_once(':')) {
+            (Some((target, value)), _) => (target, Access::Value(number(value)?)),
+            (None, Some((target, "read"))) => (target, Access::Read),
+            (None, Some((target, "write"))) => (target, Access::Write),
+            (None, Some((target, "change"))) => (target, Access::Change),
+            (None, Some((_, access))) => {
+                return Err(format!("Unknown watchpoint access: {} (expected read, write or change)", access))
+            },
+            (None, None) => (s, Access::Change),
+        };
+
+        let target = match target.trim() {
+            register if register.starts_with(['V', 'v']) => match u8::from_str_radix(&register[1..], 16) {
+                Ok(x) if register.len() == 2 => Target::Register(x),
+                _ => return Err(format!("Unknown register: {}", register)),
+            },
+            address => match address.strip_prefix('[').and_then(|address| address.strip_suffix(']')) {
+                Some(address) => Target::Memory(address_number(address)?),
+                None => return Err(format!("Unknown watchpoint target: {} (expected V0-VF or [address])", address)),
+            },
+        };
+
+        Ok(Watchpoint { target, access, action: Action::Stop })
+    }
+}
+
+/// A decimal or `0x` hex byte.
+fn number(text: &str) -> Result<u8, String> {
+    parse(text).ok().and_then(|value| u8::try_from(value).ok()).ok_or_else(|| format!("Invalid byte: {}", text.trim()))
+}
+
+/// A decimal or `0x` hex address below 0x1000.
+fn address_number(text: &str) -> Result<u16, String> {
+    parse(text).ok().filter(|address| *address < 0x1000).ok_or_else(|| format!("Invalid address: {}", text.trim()))
+}
+
+fn parse(text: &str) -> Result<u16, std::num::ParseIntError> {
+    let text = text.trim();
+    match text.strip_prefix("0x").or(text.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => text.parse(),
+    }
+}
+
+impl Display for Target {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Target::Register(x) => write!(f, "V{:X}", x),
+            Target::Memory(address) => write!(f, "[0x{:02X}]", address),
+        }
+    }
+}
+
+impl Display for Hit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let target = self.watchpoint.target;
+        match self.watchpoint.access {
+            Access::Read => write!(f, "{} read {} at PC {}", target, self.value, self.pc),
+            _ => write!(f, "{} written {} (was {}) at PC {}", target, self.value, self.old, self.pc),
+        }
+    }
+}
@@ -0,0 +1,197 @@
+/*
+Tests for the CHIP-8 core with small hand-assembled ROMs. Display tests
+compare a PBM snapshot with the file in `tests/chip8/`.
+*/
+
+use std::{fs, path::Path};
+
+use emulator::{chip8::Chip8, Step};
+
+/// Instructions to run before a ROM counts as stuck.
+const MAX_CYCLES: usize = 10_000;
+
+fn rom(words: &[u16]) -> Vec<u8> {
+    words.iter().flat_map(|word| word.to_be_bytes()).collect()
+}
+
+/// Runs until the ROM jumps to itself.
+fn run(chip8: &mut Chip8) {
+    for _ in 0..MAX_CYCLES {
+        if chip8.step().unwrap() == Step::Halted {
+            return;
+        }
+    }
+    panic!("did not halt within {} cycles (PC 0x{:03X})", MAX_CYCLES, chip8.pc());
+}
+
+#[test]
+fn draws_font_and_bcd() {
+    let mut chip8 = Chip8::new(&rom(&[
+        0x6000, // 200: V0 = 0, the digit
+        0x6101, // 202: V1 = 1, x
+        0x6201, // 204: V2 = 1, y
+        0xF029, // 206: I = sprite of V0
+        0xD125, // 208: draw it at (V1, V2)
+        0x7001, // 20A: V0 += 1
+        0x7108, // 20C: V1 += 8
+        0x3008, // 20E: after 8 digits
+        0x1216, // 210:
+        0x6101, // 212: go to the start
+        0x720A, // 214: of the next row
+        0x3010, // 216: until all 16 are drawn
+        0x1206, // 218:
+        0x60EA, // 21A: V0 = 234
+        0xA300, // 21C: I = 0x300
+        0xF033, // 21E: BCD of V0 at I
+        0xF265, // 220: V0-V2 = the digits
+        0x6301, // 222: V3 = 1, x
+        0x6416, // 224: V4 = 22, y
+        0xF029, // 226: draw V0
+        0xD345, // 228:
+        0x7306, // 22A:
+        0xF129, // 22C: draw V1
+        0xD345, // 22E:
+        0x7306, // 230:
+        0xF229, // 232: draw V2
+        0xD345, // 234:
+        0x6532, // 236: V5 = 50
+        0xD545, // 238: draw the 4 twice, which erases it
+        0xD545, // 23A:
+        0x123C, // 23C: halt
+    ]))
+    .unwrap();
+    run(&mut chip8);
+
+    let expected = fs::read_to_string(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/chip8/digits.pbm")).unwrap();
+    assert_eq!(chip8.pbm(), expected);
+    assert_eq!(chip8.register(0xF), 1, "the second draw collides");
+    assert_eq!([chip8.memory(0x300), chip8.memory(0x301), chip8.memory(0x302)], [2, 3, 4]);
+}
+
+#[test]
+fn calls_and_arithmetic() {
+    let mut chip8 = Chip8::new(&rom(&[
+        0x60FF, // 200: V0 = 255
+        0x6102, // 202: V1 = 2
+        0x8014, // 204: V0 += V1, carries
+        0x8200, // 206: V2 = V0
+        0x2210, // 208: call 210
+        0x6305, // 20A: V3 = 5
+        0x120C, // 20C: halt
+        0x0000, // 20E:
+        0x6407, // 210: V4 = 7
+        0x8416, // 212: V4 = V1 >> 1, the VIP shifts VY
+        0x00EE, // 214: return
+    ]))
+    .unwrap();
+    run(&mut chip8);
+
+    let registers: Vec<u8> = (0..5).map(|x| chip8.register(x)).collect();
+    assert_eq!(registers, [1, 2, 1, 5, 1]);
+    assert_eq!(chip8.register(0xF), 0, "the shifted out bit");
+    assert_eq!(chip8.pc(), 0x20C);
+}
+
+#[test]
+fn stores_and_loads_registers() {
+    let mut chip8 = Chip8::new(&rom(&[
+        0x6007, // 200: V0 = 7
+        0x6108, // 202: V1 = 8
+        0xA300, // 204: I = 0x300
+        0xF155, // 206: store V0-V1 at I
+        0x6000, // 208: V0 = 0
+        0x6100, // 20A: V1 = 0
+        0xA300, // 20C: I = 0x300
+        0xF165, // 20E: load V0-V1 again
+        0x1210, // 210: halt
+    ]))
+    .unwrap();
+    run(&mut chip8);
+
+    assert_eq!([chip8.register(0), chip8.register(1)], [7, 8]);
+    assert_eq!(chip8.i(), 0x302, "FX65 moves I past the last register");
+}
+
+#[test]
+fn waits_for_a_key() {
+    let mut chip8 = Chip8::new(&rom(&[
+        0xF50A, // 200: V5 = the pressed key
+        0x1202, // 202: halt
+    ]))
+    .unwrap();
+    for _ in 0..10 {
+        chip8.step().unwrap();
+    }
+    assert_eq!(chip8.pc(), 0x200);
+
+    chip8.set_key(0xB, true);
+    run(&mut chip8);
+    assert_eq!(chip8.register(5), 0xB);
+}
+
+#[test]
+fn timers_count_down() {
+    let mut chip8 = Chip8::new(&rom(&[
+        0x6003, // 200: V0 = 3
+        0xF015, // 202: delay = V0
+        0xF018, // 204: sound = V0
+        0x1206, // 206: halt
+    ]))
+    .unwrap();
+    run(&mut chip8);
+    assert!(chip8.sound());
+
+    (0..3).for_each(|_| chip8.tick());
+    assert!(!chip8.sound());
+}
+
+#[test]
+fn reports_unknown_instructions() {
+    let mut chip8 = Chip8::new(&rom(&[0x6000, 0x5121])).unwrap();
+    chip8.step().unwrap();
+    assert_eq!(chip8.step(), Err("Unknown instruction 5121 at PC 0x202".to_string()));
+}
+
+#[test]
+fn watches_registers_and_memory() {
+    let mut chip8 = Chip8::new(&rom(&[
+        0x60FF, // 200: V0 = 255
+        0x7001, // 202: V0 += 1, wraps without touching VF
+        0xA300, // 204: I = 0x300
+        0xF033, // 206: BCD of V0 at I
+        0x1208, // 208: halt
+    ]))
+    .unwrap();
+    chip8.watch("V0=0".parse().unwrap());
+    chip8.watch("[0x302]:write".parse().unwrap());
+    chip8.watch("VF".parse().unwrap());
+    run(&mut chip8);
+
+    let hits: Vec<String> = chip8.take_hits().iter().map(ToString::to_string).collect();
+    assert_eq!(hits, [
+        "V0 written 0 (was 255) at PC 514",
+        "[0x302] written 0 (was 0) at PC 518",
+    ]);
+}
+
+#[test]
+fn releases_pressed_keys() {
+    let wait = rom(&[
+        0xF00A, // 200: V0 = the pressed key
+        0x1202, // 202: halt
+    ]);
+
+    let mut chip8 = Chip8::new(&wait).unwrap();
+    chip8.press(7);
+    (0..14).for_each(|_| chip8.tick());
+    run(&mut chip8);
+    assert_eq!(chip8.register(0), 7);
+
+    let mut chip8 = Chip8::new(&wait).unwrap();
+    chip8.press(7);
+    (0..15).for_each(|_| chip8.tick());
+    for _ in 0..10 {
+        chip8.step().unwrap();
+    }
+    assert_eq!(chip8.pc(), 0x200, "the key is released after 15 frames");
+}
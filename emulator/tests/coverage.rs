@@ -0,0 +1,42 @@
+/*
+Tests for the lcov tracefile of a run.
+*/
+
+use emulator::{coverage::Coverage, Machine, Step};
+use exe::{DebugInfo, Executable, SourceLine};
+
+fn line(line: Option<usize>) -> SourceLine {
+    SourceLine { file: None, line }
+}
+
+#[test]
+fn writes_lines_and_branches() {
+    // setrc V0 1, setrc V1 1, ifeq V0 V1, setrc V2 1 (skipped), setrc V3 1
+    let program = Executable::new(vec![0x60, 0x01, 0x61, 0x01, 0x10, 0x01, 0x62, 0x01, 0x63, 0x01]);
+    let debug = DebugInfo {
+        source: Some("count.asm".to_string()),
+        // the last instruction has no line and is left out
+        lines: vec![line(Some(1)), line(Some(2)), line(Some(3)), line(Some(4)), line(None)],
+        labels: Vec::new(),
+    };
+
+    let mut machine = Machine::with_observer(&program, Coverage::new()).unwrap();
+    while machine.step().unwrap() != Step::Halted {}
+
+    let expected = "\
+TN:
+SF:count.asm
+BRDA:3,2,0,0
+BRDA:3,2,1,1
+BRF:2
+BRH:1
+DA:1,1
+DA:2,1
+DA:3,1
+DA:4,0
+LF:4
+LH:3
+end_of_record
+";
+    assert_eq!(machine.observer().lcov(&program, &debug), expected);
+}
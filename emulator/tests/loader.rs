@@ -0,0 +1,83 @@
+/*
+Tests for detecting and decoding the program formats of `emulator --format`.
+*/
+
+use emulator::{loader::{detect, load, Format}, ROM_SIZE};
+
+const IHEX: &str = ":0400000061FF120189\n:00000001FF\n";
+const SREC: &str = "S107000061FF120185\nS9030000FC\n";
+
+#[test]
+fn detects_records_by_a_valid_first_line() {
+    assert_eq!(detect(IHEX.as_bytes()), Format::Ihex);
+    assert_eq!(detect(SREC.as_bytes()), Format::Srec);
+    assert_eq!(detect(IHEX.replace('\n', "\r\n").as_bytes()), Format::Ihex);
+    assert_eq!(detect(b"61FF 1201\n"), Format::Hex);
+}
+
+#[test]
+fn falls_back_to_raw_without_a_valid_record() {
+    // a binary that happens to start with ':' or "S1"
+    assert_eq!(detect(&[b':', 0x61, 0xFF, b'\n']), Format::Raw);
+    assert_eq!(detect(&[b'S', b'1', 0x00, 0x12]), Format::Raw);
+    // wrong checksum, wrong length, no line terminator
+    assert_eq!(detect(b":0400000061FF120188\n"), Format::Raw);
+    assert_eq!(detect(b":0500000061FF120188\n"), Format::Raw);
+    assert_eq!(detect(b":0400000061FF120189"), Format::Raw);
+    assert_eq!(detect(b"S107000061FF120186\n"), Format::Raw);
+    // leading whitespace is part of the first line
+    assert_eq!(detect(format!(" {}", IHEX).as_bytes()), Format::Raw);
+    assert_eq!(detect(format!("\n{}", SREC).as_bytes()), Format::Raw);
+}
+
+#[test]
+fn loads_records() {
+    for (text, format) in [(IHEX, Format::Ihex), (SREC, Format::Srec)] {
+        let program = load(text.as_bytes(), None, ROM_SIZE).unwrap();
+        assert_eq!(program.code, [0x61, 0xFF, 0x12, 0x01], "{}", format);
+    }
+}
+
+#[test]
+fn rejects_checksum_mismatches() {
+    let ihex = ":0400000061FF120188\n:00000001FF\n";
+    assert_eq!(
+        load(ihex.as_bytes(), Some(Format::Ihex), ROM_SIZE).unwrap_err(),
+        "line 1: checksum mismatch (expected 89, found 88)"
+    );
+    let srec = "S107000061FF120185\nS9030000FD\n";
+    assert_eq!(
+        load(srec.as_bytes(), Some(Format::Srec), ROM_SIZE).unwrap_err(),
+        "line 2: checksum mismatch (expected FC, found FD)"
+    );
+}
+
+#[test]
+fn rejects_missing_end_records() {
+    assert_eq!(
+        load(b":0400000061FF120189\n", None, ROM_SIZE).unwrap_err(),
+        "missing end of file record"
+    );
+    assert_eq!(
+        load(b"S107000061FF120185\n", None, ROM_SIZE).unwrap_err(),
+        "missing termination record"
+    );
+}
+
+#[test]
+fn loads_hex_text() {
+    let program = load(b"61ff 1201\n\n  0A\n", Some(Format::Hex), ROM_SIZE).unwrap();
+    assert_eq!(program.code, [0x61, 0xFF, 0x12, 0x01, 0x0A]);
+}
+
+#[test]
+fn rejects_malformed_hex_text() {
+    assert_eq!(
+        load(b"61FF\n12G1\n", Some(Format::Hex), ROM_SIZE).unwrap_err(),
+        "line 2: 'G' in 12G1 is not a hex digit"
+    );
+    assert_eq!(
+        load(b"61FF 120\n", Some(Format::Hex), ROM_SIZE).unwrap_err(),
+        "line 1: 120 has an odd number of hex digits"
+    );
+}
@@ -0,0 +1,89 @@
+/*
+Tests for the events observers get and how pairs, options and references
+pass them on.
+*/
+
+use emulator::{observer::Observer, Machine, Step};
+use exe::Executable;
+
+/// Keeps every event as text.
+#[derive(Debug, Default)]
+struct Recorder {
+    events: Vec<String>,
+}
+
+impl Observer for Recorder {
+    fn on_fetch(&mut self, pc: usize, instruction: [u8; 2]) {
+        self.events.push(format!("fetch {} {:02X}{:02X}", pc, instruction[0], instruction[1]));
+    }
+
+    fn on_execute(&mut self, pc: usize, instruction: [u8; 2], next: usize) {
+        self.events.push(format!("execute {} {:02X}{:02X} -> {}", pc, instruction[0], instruction[1], next));
+    }
+
+    fn on_mem_read(&mut self, address: usize, value: u8) {
+        self.events.push(format!("read [{}] = {}", address, value));
+    }
+
+    fn on_mem_write(&mut self, address: usize, old: u8, value: u8) {
+        self.events.push(format!("write [{}] = {} over {}", address, value, old));
+    }
+
+    fn on_output(&mut self, value: u8) {
+        self.events.push(format!("output {}", value));
+    }
+
+    fn on_halt(&mut self, pc: usize) {
+        self.events.push(format!("halt {}", pc));
+    }
+}
+
+fn run<O: Observer>(observer: O) -> O {
+    // setrm V0 0x10, setrc V0 5, setmr V0 0x20, setmr V0 0xFF
+    let mut program = Executable::new(vec![0x50, 0x10, 0x60, 0x05, 0x80, 0x20, 0x80, 0xFF]);
+    program.data = vec![0; 0x11];
+    program.data[0x10] = 7;
+    let mut machine = Machine::with_observer(&program, observer).unwrap();
+    while machine.step().unwrap() != Step::Halted {}
+    machine.into_observer()
+}
+
+const EVENTS: [&str; 13] = [
+    "fetch 0 5010",
+    "read [16] = 7",
+    "execute 0 5010 -> 1",
+    "fetch 1 6005",
+    "execute 1 6005 -> 2",
+    "fetch 2 8020",
+    "write [32] = 5 over 0",
+    "execute 2 8020 -> 3",
+    "fetch 3 80FF",
+    "write [255] = 5 over 0",
+    "execute 3 80FF -> 4",
+    "output 5",
+    "halt 4",
+];
+
+#[test]
+fn reports_every_event() {
+    assert_eq!(run(Recorder::default()).events, EVENTS);
+}
+
+#[test]
+fn a_pair_passes_every_event_to_both() {
+    let (first, second) = run((Recorder::default(), Recorder::default()));
+    assert_eq!(first.events, EVENTS);
+    assert_eq!(second.events, EVENTS);
+
+    let ((first, none), second) = run(((Recorder::default(), None::<Recorder>), Some(Recorder::default())));
+    assert_eq!(first.events, EVENTS);
+    assert!(none.is_none());
+    assert_eq!(second.unwrap().events, EVENTS);
+}
+
+#[test]
+fn a_reference_passes_events_on() {
+    let mut recorder = Recorder::default();
+    run(&mut recorder);
+    assert_eq!(recorder.events, EVENTS);
+}
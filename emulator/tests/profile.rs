@@ -0,0 +1,75 @@
+/*
+Tests for the instruction counts, loops and reports of a profiled run.
+*/
+
+use emulator::{profile::Profile, Machine, Step};
+use exe::{DebugInfo, Executable, SourceLine};
+
+/// Counts V0 down from 3 in a loop.
+const COUNTDOWN: [u8; 14] = [
+    0x60, 0x03, // setrc V0 3
+    0x61, 0x01, // setrc V1 1
+    0x62, 0x00, // setrc V2 0
+    0x91, 0x01, // loop: sub V0 V1
+    0x10, 0x02, // ifeq V0 V2
+    0x00, 0x03, // jump loop
+    0x63, 0x01, // setrc V3 1
+];
+
+fn profile(program: &Executable) -> Profile {
+    let mut machine = Machine::with_observer(program, Profile::new()).unwrap();
+    while machine.step().unwrap() != Step::Halted {}
+    machine.into_observer()
+}
+
+#[test]
+fn counts_every_instruction_and_loop() {
+    let profile = profile(&Executable::new(COUNTDOWN.to_vec()));
+    let counts: Vec<u64> = (0..8).map(|pc| profile.count(pc)).collect();
+    assert_eq!(counts, [1, 1, 1, 3, 3, 2, 1, 0]);
+    assert_eq!(profile.total(), 12);
+    assert_eq!(profile.iterations(5, 3), 2);
+    assert_eq!(profile.iterations(4, 3), 0);
+}
+
+#[test]
+fn reports_counts_and_loops() {
+    let program = Executable::new(COUNTDOWN.to_vec());
+    let report = profile(&program).report(&program);
+    // without debug info the location column is empty
+    let lines: Vec<&str> = report.lines().map(str::trim_end).collect();
+    assert_eq!(lines, [
+        "profile: 12 instructions",
+        "",
+        "   count       %   PC  instruction        location",
+        "       3   25.0%    3  sub V0 V1",
+        "       3   25.0%    4  ifeq V0 V2",
+        "       2   16.7%    5  jump 0x03",
+        "       1    8.3%    0  setrc V0 0x03",
+        "       1    8.3%    1  setrc V1 0x01",
+        "       1    8.3%    2  setrc V2 0x00",
+        "       1    8.3%    6  setrc V3 0x01",
+        "",
+        "by opcode class:",
+        "       4   33.3%  Reg",
+        "       3   25.0%  Cond",
+        "       3   25.0%  Math",
+        "       2   16.7%  Flow",
+        "",
+        "hot loops:",
+        "       2 iterations  PC 3..=5 (66.7% of instructions)",
+    ]);
+}
+
+#[test]
+fn folds_by_label() {
+    let mut program = Executable::new(COUNTDOWN.to_vec());
+    program.debug = Some(DebugInfo {
+        source: Some("countdown.asm".to_string()),
+        lines: (1..=7).map(|line| SourceLine { file: None, line: Some(line) }).collect(),
+        labels: vec![("start".to_string(), 0), ("loop".to_string(), 3), ("done".to_string(), 6)],
+    });
+    let profile = profile(&program);
+    assert_eq!(profile.folded(&program), "countdown.asm;start 3\ncountdown.asm;loop 8\ncountdown.asm;done 1\n");
+    assert_eq!(profile.folded(&Executable::new(COUNTDOWN.to_vec())).lines().next(), Some("program;PC 0 1"));
+}
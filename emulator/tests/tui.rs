@@ -0,0 +1,77 @@
+/*
+Tests for the screen of `emulator --tui`, rendered without a terminal.
+*/
+
+use emulator::{tui::frame, Machine};
+use exe::{DebugInfo, Executable, SourceLine};
+
+/// The lines of a screen without the ANSI escape codes.
+fn plain(screen: &str) -> Vec<String> {
+    let mut text = String::new();
+    let mut chars = screen.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // ESC [ parameters, ended by a letter
+            chars.by_ref().skip(1).find(char::is_ascii_alphabetic);
+        }
+        else {
+            text.push(c);
+        }
+    }
+    text.split("\r\n").map(|line| line.trim_end().to_string()).collect()
+}
+
+#[test]
+fn lays_out_the_screen() {
+    // setrc V0 5, setmr V0 0xFF, jump 0
+    let program = Executable::new(vec![0x60, 0x05, 0x80, 0xFF, 0x00, 0x00]);
+    let mut machine = Machine::new(&program).unwrap();
+    machine.step().unwrap();
+
+    let screen = frame(&program, &mut machine, 10, 70);
+    assert_eq!(plain(&screen), [
+        "PC 1  cycles 0                                                 paused",
+        "    0  setrc V0 0x05       | registers",
+        ">   1  setmr V0 0xFF       | V0 05 V1 00 V2 00 V3 00 V4 00 V5 00 V6 00 V7 00",
+        "    2  jump 0x00           | V8 00 V9 00 VA 00 VB 00 VC 00 VD 00 VE 00 VF 00",
+        "                           | memory",
+        "                           | 00: 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00",
+        "                           | 10: 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00",
+        "output:",
+        "s: step  r: run  p: pause  b: breakpoint  j/k: select  q: quit",
+    ]);
+    // the line at the PC is in reverse video
+    assert!(screen.contains("> \x1b[7m  1  setmr V0 0xFF      \x1b[0m |"), "{:?}", screen);
+}
+
+#[test]
+fn scrolls_to_the_pc() {
+    let program = Executable::new([0x60, 0x01].repeat(20));
+    let mut machine = Machine::new(&program).unwrap();
+    machine.set_pc(15);
+
+    let lines = plain(&frame(&program, &mut machine, 8, 60));
+    assert_eq!(lines.len(), 7);
+    let disassembly: Vec<&str> = lines[1..5].iter().map(|line| &line[..26]).collect();
+    assert_eq!(disassembly, [
+        "   12  setrc V0 0x01      ",
+        "   13  setrc V0 0x01      ",
+        "   14  setrc V0 0x01      ",
+        ">  15  setrc V0 0x01      ",
+    ]);
+}
+
+#[test]
+fn names_the_source_line_and_fits_the_width() {
+    let mut program = Executable::new(vec![0x60, 0x05]);
+    program.debug = Some(DebugInfo {
+        source: Some("main.asm".to_string()),
+        lines: vec![SourceLine { file: None, line: Some(3) }],
+        labels: vec![("start".to_string(), 0)],
+    });
+    let mut machine = Machine::new(&program).unwrap();
+
+    let lines = plain(&frame(&program, &mut machine, 6, 44));
+    assert_eq!(lines[0], "PC 0  cycles 0  main.asm:3 (start)   paused");
+    assert_eq!(lines.last().unwrap(), "s: step  r: run  p: pause  b: breakpoint  j/");
+}
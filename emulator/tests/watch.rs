@@ -0,0 +1,76 @@
+/*
+Tests for parsing watchpoint specs and for the hits of a watched machine.
+*/
+
+use emulator::{watch::{Access, Action, Target, Watchpoint}, Machine};
+use exe::Executable;
+
+fn watchpoint(spec: &str) -> Watchpoint {
+    spec.parse().unwrap()
+}
+
+fn error(spec: &str) -> String {
+    spec.parse::<Watchpoint>().unwrap_err()
+}
+
+#[test]
+fn parses_register_specs() {
+    assert_eq!(
+        watchpoint("VF=1"),
+        Watchpoint { target: Target::Register(0xF), access: Access::Value(1), action: Action::Stop }
+    );
+    assert_eq!(watchpoint("V3:read").access, Access::Read);
+    assert_eq!(watchpoint("v3:write").access, Access::Write);
+    assert_eq!(watchpoint("V3:change").access, Access::Change);
+    assert_eq!(watchpoint("Va").target, Target::Register(0xA));
+    assert_eq!(watchpoint("V0=0xFF").access, Access::Value(0xFF));
+}
+
+#[test]
+fn parses_memory_specs() {
+    assert_eq!(
+        watchpoint("[0x20]"),
+        Watchpoint { target: Target::Memory(0x20), access: Access::Change, action: Action::Stop }
+    );
+    assert_eq!(watchpoint("[32]:read").target, Target::Memory(32));
+    assert_eq!(watchpoint("[0xFFF]").target, Target::Memory(0xFFF));
+}
+
+#[test]
+fn rejects_malformed_specs() {
+    assert_eq!(error("V10"), "Unknown register: V10");
+    assert_eq!(error("VG"), "Unknown register: VG");
+    assert_eq!(error("V"), "Unknown register: V");
+    assert_eq!(error("V3:peek"), "Unknown watchpoint access: peek (expected read, write or change)");
+    assert_eq!(error("VF=256"), "Invalid byte: 256");
+    assert_eq!(error("VF="), "Invalid byte: ");
+    assert_eq!(error("[0x1000]"), "Invalid address: 0x1000");
+    assert_eq!(error("[twenty]"), "Invalid address: twenty");
+    assert_eq!(error("0x20"), "Unknown watchpoint target: 0x20 (expected V0-VF or [address])");
+    assert_eq!(error("[0x20"), "Unknown watchpoint target: [0x20 (expected V0-VF or [address])");
+}
+
+#[test]
+fn reports_hits_of_a_running_machine() {
+    // setrc V1 5, setrc V1 5, setmr V1 0x20
+    let program = Executable::new(vec![0x61, 0x05, 0x61, 0x05, 0x81, 0x20]);
+    let mut machine = Machine::new(&program).unwrap();
+    machine.watch(watchpoint("V1"));
+    machine.watch(watchpoint("V1:read"));
+    machine.watch(watchpoint("[0x20]=5"));
+    for _ in 0..3 {
+        machine.step().unwrap();
+    }
+
+    let hits = machine.take_hits().iter().map(ToString::to_string).collect::<Vec<_>>();
+    assert_eq!(hits, ["V1 written 5 (was 0) at PC 0", "V1 read 5 at PC 2", "[0x20] written 5 (was 0) at PC 2"]);
+    assert!(machine.take_hits().is_empty());
+}
+
+#[test]
+fn masks_register_numbers() {
+    let mut machine = Machine::new(&Executable::new(vec![0x40, 0x00])).unwrap();
+    machine.set_register(0x13, 7);
+    assert_eq!(machine.register(3), 7);
+    assert_eq!(machine.register(0xF3), 7);
+}
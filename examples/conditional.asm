@@ -0,0 +1,20 @@
+; Conditional assembly and an included file.
+
+.include "lib/constants.inc"
+
+.if @VERBOSE
+    setrc V1 1
+    print V1
+.endif
+
+.ifdef @ANSWER
+    setrc V1 @ANSWER
+.else
+    setrc V1 0
+.endif
+    print V1
+
+.if @ANSWER > 40
+    setrc V1 @ANSWER * 2 - @ANSWER + 1
+    print V1
+.endif
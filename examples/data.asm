@@ -0,0 +1,15 @@
+; Prints the bytes of a zero terminated string from the .data section.
+
+.data
+@message
+    .string "Hi!"
+
+.code
+    setrm V1 @message
+    print V1
+    setrm V1 @message + 1
+    print V1
+    setrm V1 @message + 2
+    print V1
+    setrm V1 @message + 3
+    print V1
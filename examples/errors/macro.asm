@@ -0,0 +1,7 @@
+; Errors inside a macro point at the macro body and at where it was used.
+
+.macro load r value
+    setrc \r \value
+.endm
+
+    load V1 300
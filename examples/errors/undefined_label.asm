@@ -0,0 +1,4 @@
+; The assembler reports labels that are never defined.
+
+    setrc V1 1
+    jump @nowhere
@@ -0,0 +1,21 @@
+; Includes and macro definitions inside .if blocks. Skipped branches are not
+; read at all, so the file that does not exist and the first definition of
+; show are never seen.
+
+.ifdef @DEBUG
+.include "lib/debug.inc"
+.endif
+
+.if 0
+.macro show value
+    setrc V1 \value + 100
+    print V1
+.endm
+.else
+.macro show value
+    setrc V1 \value
+    print V1
+.endm
+.endif
+
+    show 7
@@ -0,0 +1,13 @@
+; Macros with parameters and labels local to each expansion.
+
+.macro countdown r
+@.loop
+    print \r
+    dec \r
+    bne \r V0 @.loop
+.endm
+
+    setrc V1 3
+    countdown V1
+    setrc V2 2
+    countdown V2
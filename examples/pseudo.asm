@@ -0,0 +1,16 @@
+; Pseudo-instructions and branches: counts V1 from 0 to 5 and prints the
+; numbers that are greater than 2.
+
+.alias count V1
+.alias limit V2
+.alias three V3
+
+    clr count
+    mov limit 5
+    mov three 3
+@loop
+    blt count three @next
+    print count
+@next
+    inc count
+    ble count limit @loop
@@ -0,0 +1,22 @@
+; .test blocks next to the routine they test. asmtest runs them, and so does
+; the examples harness after the program.
+
+    setrc V1 2
+    setrc VE @end           ; the routine returns to the end, which halts
+    jump @countdown
+
+; prints V1 down to 1 and returns through VE
+@countdown
+    print V1
+    dec V1
+    ifeq V1 V0
+    jump @countdown
+    setpcr VE
+@end
+
+.test "countdown prints 3 2 1"
+    .set V1 3
+    .call @countdown VE
+    .expect V1 0
+    .expect output 3 2 1
+.endtest
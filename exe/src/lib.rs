@@ -0,0 +1,349 @@
+/*
+Executable container format shared by the assembler and the emulator.
+
+All multi-byte fields are little endian.
+
+| Field | Size | Description |
+|-|-|-|
+| magic | 4 | `EMU8` |
+| version | 1 | ISA version the program was assembled for |
+| entry | 1 | PC to start execution at |
+| sections | 1 | Number of sections that follow |
+
+Every section is
+
+| Field | Size | Description |
+|-|-|-|
+| kind | 1 | 1 = code (ROM), 2 = data (`memory`), 3 = symbols, 7 = debug info |
+| address | 2 | Load address of the section |
+| length | 2 | Number of bytes in the section |
+| bytes | length | Section content |
+
+The symbol section holds entries of a 1 byte value, a 1 byte name length and
+the UTF-8 name. Negative values are stored as two's complement. Unknown section kinds are skipped when loading.
+
+The debug section (`assembler -g`) maps instructions back to the source:
+
+| Field | Size | Description |
+|-|-|-|
+| files | 2 | Number of file names, then for each a 2 byte length and the UTF-8 name. File 0 is the assembled file, empty if it was not a file |
+| lines | 2 | Number of instructions, then for each a 2 byte file index and a 4 byte line, 0 if the instruction has no source line |
+| labels | 2 | Number of labels in the code, then for each a 1 byte PC, a 1 byte name length and the UTF-8 name |
+
+Relocatable object files from the assembler use the same sections, see
+`object.rs`.
+*/
+
+pub mod object;
+
+/// Magic bytes at the start of every executable.
+pub const MAGIC: [u8; 4] = *b"EMU8";
+
+/// ISA version written by the assembler and accepted by the emulator.
+pub const ISA_VERSION: u8 = 1;
+
+/// Size of `memory` in bytes, the data section can not be larger.
+pub const MEMORY_SIZE: usize = 256;
+
+const HEADER_SIZE: usize = 7;
+const SECTION_HEADER_SIZE: usize = 5;
+
+const SECTION_CODE: u8 = 1;
+const SECTION_DATA: u8 = 2;
+const SECTION_SYMBOLS: u8 = 3;
+const SECTION_DEBUG: u8 = 7;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub name: String,
+    pub value: u8,
+}
+
+/// A loadable program. `code` is the ROM image and `data` the initial content
+/// of `memory`, both starting at address 0.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Executable {
+    pub entry: u8,
+    pub code: Vec<u8>,
+    pub data: Vec<u8>,
+    pub symbols: Vec<Symbol>,
+    pub debug: Option<DebugInfo>,
+}
+
+/// Where the code of a program comes from, for debuggers and profilers.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DebugInfo {
+    /// File the program was assembled from, `None` for source from memory.
+    pub source: Option<String>,
+    /// Source line of every instruction, indexed by PC.
+    pub lines: Vec<SourceLine>,
+    /// Labels in the code as (name, PC), sorted by PC.
+    pub labels: Vec<(String, u8)>,
+}
+
+/// A line in the source or in an included file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLine {
+    /// Included file the line is in, `None` for the assembled source.
+    pub file: Option<String>,
+    /// Line starting at 1, `None` for code that does not come from a line
+    /// of source.
+    pub line: Option<usize>,
+}
+
+impl DebugInfo {
+    /// The last label at or before `pc`, which is the routine or loop the
+    /// instruction is part of.
+    pub fn label(&self, pc: usize) -> Option<&str> {
+        self.labels
+            .iter()
+            .rev()
+            .find(|(_, label)| *label as usize <= pc)
+            .map(|(name, _)| name.as_str())
+    }
+
+    /// The file and line of the instruction at `pc`, like `fib.asm:7`.
+    pub fn location(&self, pc: usize) -> Option<String> {
+        let line = self.lines.get(pc)?;
+        let file = line.file.as_deref().or(self.source.as_deref()).unwrap_or("line");
+        Some(format!("{}:{}", file, line.line?))
+    }
+
+    fn to_bytes(&self) -> Result<Vec<u8>, String> {
+        let mut files = vec![self.source.clone().unwrap_or_default()];
+        let mut lines = Vec::new();
+        for line in &self.lines {
+            let index = match &line.file {
+                None => 0,
+                Some(file) => files.iter().skip(1).position(|known| known == file).map_or_else(
+                    || {
+                        files.push(file.clone());
+                        files.len() - 1
+                    },
+                    |index| index + 1,
+                ),
+            };
+            lines.extend_from_slice(&u16_field(index, "Number of source files")?);
+            let number = line.line.unwrap_or(0);
+            let number = u32::try_from(number).map_err(|_| format!("Line {} is too large for the debug info", number))?;
+            lines.extend_from_slice(&number.to_le_bytes());
+        }
+
+        let mut bytes = u16_field(files.len(), "Number of source files")?.to_vec();
+        for file in &files {
+            bytes.extend_from_slice(&u16_field(file.len(), "Length of a file name")?);
+            bytes.extend_from_slice(file.as_bytes());
+        }
+        bytes.extend_from_slice(&u16_field(self.lines.len(), "Number of debug lines")?);
+        bytes.extend_from_slice(&lines);
+        bytes.extend_from_slice(&u16_field(self.labels.len(), "Number of debug labels")?);
+        for (name, pc) in &self.labels {
+            bytes.push(*pc);
+            push_name(&mut bytes, name)?;
+        }
+        Ok(bytes)
+    }
+
+    fn from_bytes(input: &[u8]) -> Result<Self, String> {
+        let mut reader = Reader { input };
+
+        let mut files = Vec::new();
+        for _ in 0..reader.u16()? {
+            let length = reader.u16()?;
+            files.push(reader.text(length)?);
+        }
+
+        let mut debug = DebugInfo::default();
+        for _ in 0..reader.u16()? {
+            let file = match reader.u16()? {
+                0 => None,
+                index => Some(files.get(index).ok_or("Debug line refers to an unknown file")?.clone()),
+            };
+            let line = reader.bytes(4)?;
+            let line = u32::from_le_bytes([line[0], line[1], line[2], line[3]]) as usize;
+            debug.lines.push(SourceLine { file, line: (line != 0).then_some(line) });
+        }
+        for _ in 0..reader.u16()? {
+            let header = reader.bytes(2)?;
+            debug.labels.push((reader.text(header[1] as usize)?, header[0]));
+        }
+
+        debug.source = files.into_iter().next().filter(|source| !source.is_empty());
+        Ok(debug)
+    }
+}
+
+impl Executable {
+    /// An executable with only code, starting at PC 0.
+    pub fn new(code: Vec<u8>) -> Self {
+        Self { code, ..Default::default() }
+    }
+
+    /// Checks if `input` starts with the executable magic.
+    pub fn is_executable(input: &[u8]) -> bool {
+        input.starts_with(&MAGIC)
+    }
+
+    /// The executable as a file. Fails if a name or section is too long for
+    /// its length field.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, String> {
+        let mut sections = vec![(SECTION_CODE, self.code.clone())];
+        if !self.data.is_empty() {
+            sections.push((SECTION_DATA, self.data.clone()));
+        }
+        if !self.symbols.is_empty() {
+            let mut table = Vec::new();
+            for symbol in &self.symbols {
+                table.push(symbol.value);
+                push_name(&mut table, &symbol.name)?;
+            }
+            sections.push((SECTION_SYMBOLS, table));
+        }
+        if let Some(debug) = &self.debug {
+            sections.push((SECTION_DEBUG, debug.to_bytes()?));
+        }
+
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(ISA_VERSION);
+        bytes.push(self.entry);
+        write_sections(&mut bytes, sections)?;
+        Ok(bytes)
+    }
+
+    pub fn from_bytes(input: &[u8]) -> Result<Self, String> {
+        if !Self::is_executable(input) {
+            return Err("Not an executable (bad magic)".to_string());
+        }
+        if input.len() < HEADER_SIZE {
+            return Err("Executable header is truncated".to_string());
+        }
+        if input[4] != ISA_VERSION {
+            return Err(format!(
+                "Unsupported ISA version {} (expected {})",
+                input[4], ISA_VERSION
+            ));
+        }
+
+        let mut executable = Executable {
+            entry: input[5],
+            ..Default::default()
+        };
+
+        for (kind, address, content) in read_sections(&input[HEADER_SIZE - 1..])? {
+            match kind {
+                SECTION_CODE => place(&mut executable.code, address, content),
+                SECTION_DATA => place(&mut executable.data, address, content),
+                SECTION_SYMBOLS => executable.symbols.extend(read_symbols(content)?),
+                SECTION_DEBUG => executable.debug = Some(DebugInfo::from_bytes(content)?),
+                _ => {},
+            }
+        }
+
+        Ok(executable)
+    }
+}
+
+/// Reads the fields of a section from the front.
+struct Reader<'a> {
+    input: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, count: usize) -> Result<&'a [u8], String> {
+        let (bytes, rest) = self.input.split_at_checked(count).ok_or("Debug section is truncated")?;
+        self.input = rest;
+        Ok(bytes)
+    }
+
+    fn u16(&mut self) -> Result<usize, String> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]) as usize)
+    }
+
+    fn text(&mut self, length: usize) -> Result<String, String> {
+        String::from_utf8(self.bytes(length)?.to_vec()).map_err(|_| "Debug name is not valid UTF-8".to_string())
+    }
+}
+
+/// Appends the section count and the sections as (kind, content), all loaded
+/// at address 0.
+fn write_sections(bytes: &mut Vec<u8>, sections: Vec<(u8, Vec<u8>)>) -> Result<(), String> {
+    bytes.push(u8::try_from(sections.len()).map_err(|_| format!("{} sections are too many", sections.len()))?);
+    for (kind, content) in sections {
+        bytes.push(kind);
+        bytes.extend_from_slice(&0u16.to_le_bytes());
+        bytes.extend_from_slice(&u16_field(content.len(), &format!("Size of section {}", kind))?);
+        bytes.extend_from_slice(&content);
+    }
+    Ok(())
+}
+
+/// Appends a 1 byte name length and the name.
+fn push_name(bytes: &mut Vec<u8>, name: &str) -> Result<(), String> {
+    let length = u8::try_from(name.len()).map_err(|_| format!("Name {} is longer than 255 bytes", name))?;
+    bytes.push(length);
+    bytes.extend_from_slice(name.as_bytes());
+    Ok(())
+}
+
+/// A count or length as a 2 byte field, `what` names it in the error.
+fn u16_field(value: usize, what: &str) -> Result<[u8; 2], String> {
+    u16::try_from(value)
+        .map(u16::to_le_bytes)
+        .map_err(|_| format!("{} is {}, more than {}", what, value, u16::MAX))
+}
+
+/// A section as (kind, address, content).
+type RawSection<'a> = (u8, usize, &'a [u8]);
+
+/// Reads the section count at the start of `input` and the sections after
+/// it.
+fn read_sections(input: &[u8]) -> Result<Vec<RawSection<'_>>, String> {
+    let mut sections = Vec::new();
+    let mut offset = 1;
+    for section in 0..input[0] {
+        let header = input
+            .get(offset..offset + SECTION_HEADER_SIZE)
+            .ok_or_else(|| format!("Section {} header is truncated", section))?;
+        let kind = header[0];
+        let address = u16::from_le_bytes([header[1], header[2]]) as usize;
+        let length = u16::from_le_bytes([header[3], header[4]]) as usize;
+        offset += SECTION_HEADER_SIZE;
+
+        let content = input
+            .get(offset..offset + length)
+            .ok_or_else(|| format!("Section {} is truncated", section))?;
+        offset += length;
+
+        sections.push((kind, address, content));
+    }
+    Ok(sections)
+}
+
+/// Copies a section into an image at its load address.
+fn place(image: &mut Vec<u8>, address: usize, content: &[u8]) {
+    let end = address + content.len();
+    if image.len() < end {
+        image.resize(end, 0);
+    }
+    image[address..end].copy_from_slice(content);
+}
+
+fn read_symbols(mut table: &[u8]) -> Result<Vec<Symbol>, String> {
+    let mut symbols = Vec::new();
+    while let [value, length, rest @ ..] = table {
+        let name = rest
+            .get(..*length as usize)
+            .ok_or("Symbol table is truncated")?;
+        symbols.push(Symbol {
+            name: String::from_utf8(name.to_vec())
+                .map_err(|_| "Symbol name is not valid UTF-8")?,
+            value: *value,
+        });
+        table = &rest[*length as usize..];
+    }
+    if !table.is_empty() {
+        return Err("Symbol table is truncated".to_string());
+    }
+    Ok(symbols)
+}
@@ -0,0 +1,271 @@
+/*
+Relocatable object files written by `assembler -c` and combined by the linker.
+
+| Field | Size | Description |
+|-|-|-|
+| magic | 4 | `EMUO` |
+| version | 1 | ISA version the module was assembled for |
+| flags | 1 | Bit 0 is set if the module has an entry point |
+| entry | 1 | Entry point relative to the start of the module's code |
+| sections | 1 | Number of sections that follow |
+
+The sections have the same layout as in an executable, with the kinds
+
+| Kind | Content |
+|-|-|
+| 1 | Code of the module, as if it was loaded at PC 0 |
+| 2 | Data of the module, as if it was loaded at address 0 |
+| 4 | Exports: base (1), value (2), name length (1), name |
+| 5 | Imports: name length (1), name |
+| 6 | Relocations: section (1), offset (2), target (1), import (2), addend (2) |
+
+A base or target is 0 = absolute, 1 = relative to the module's code (in
+instructions), 2 = relative to the module's data or 3 = an import, given by
+its index. A relocation says that the byte at `offset` in the code (1) or
+data (2) section is the value of its target plus `addend`.
+*/
+
+use crate::{push_name, read_sections, write_sections, ISA_VERSION, SECTION_CODE, SECTION_DATA};
+
+/// Magic bytes at the start of every object file.
+pub const MAGIC: [u8; 4] = *b"EMUO";
+
+const HEADER_SIZE: usize = 8;
+const FLAG_ENTRY: u8 = 1;
+
+const SECTION_EXPORTS: u8 = 4;
+const SECTION_IMPORTS: u8 = 5;
+const SECTION_RELOCATIONS: u8 = 6;
+
+const RELOCATION_SIZE: usize = 8;
+
+/// What a value is relative to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Base {
+    Absolute,
+    Code,
+    Data,
+}
+
+/// A section that relocations patch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Section {
+    Code,
+    Data,
+}
+
+/// What a relocated byte refers to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    Code,
+    Data,
+    /// Index into the imports of the module.
+    Import(u16),
+}
+
+/// A symbol the module defines for other modules (`.global`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Export {
+    pub name: String,
+    pub base: Base,
+    pub value: i16,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Relocation {
+    pub section: Section,
+    /// Byte offset in the section.
+    pub offset: u16,
+    pub target: Target,
+    pub addend: i16,
+}
+
+/// An assembled module whose code and data can be placed anywhere.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Object {
+    pub entry: Option<u8>,
+    pub code: Vec<u8>,
+    pub data: Vec<u8>,
+    pub exports: Vec<Export>,
+    /// Symbols used by the module but defined elsewhere (`.extern`).
+    pub imports: Vec<String>,
+    pub relocations: Vec<Relocation>,
+}
+
+impl Object {
+    /// Checks if `input` starts with the object file magic.
+    pub fn is_object(input: &[u8]) -> bool {
+        input.starts_with(&MAGIC)
+    }
+
+    /// The module as an object file. Fails if a name or section is too long
+    /// for its length field.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, String> {
+        let mut exports = Vec::new();
+        for export in &self.exports {
+            exports.push(base_to_u8(export.base));
+            exports.extend_from_slice(&export.value.to_le_bytes());
+            push_name(&mut exports, &export.name)?;
+        }
+
+        let mut imports = Vec::new();
+        for import in &self.imports {
+            push_name(&mut imports, import)?;
+        }
+
+        let mut relocations = Vec::new();
+        self.relocations.iter().for_each(|relocation| {
+            relocations.push(match relocation.section {
+                Section::Code => SECTION_CODE,
+                Section::Data => SECTION_DATA,
+            });
+            relocations.extend_from_slice(&relocation.offset.to_le_bytes());
+            let (target, import) = match relocation.target {
+                Target::Code => (1, 0),
+                Target::Data => (2, 0),
+                Target::Import(import) => (3, import),
+            };
+            relocations.push(target);
+            relocations.extend_from_slice(&import.to_le_bytes());
+            relocations.extend_from_slice(&relocation.addend.to_le_bytes());
+        });
+
+        let sections = vec![
+            (SECTION_CODE, self.code.clone()),
+            (SECTION_DATA, self.data.clone()),
+            (SECTION_EXPORTS, exports),
+            (SECTION_IMPORTS, imports),
+            (SECTION_RELOCATIONS, relocations),
+        ];
+
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(ISA_VERSION);
+        bytes.push(if self.entry.is_some() { FLAG_ENTRY } else { 0 });
+        bytes.push(self.entry.unwrap_or(0));
+        write_sections(&mut bytes, sections)?;
+        Ok(bytes)
+    }
+
+    pub fn from_bytes(input: &[u8]) -> Result<Self, String> {
+        if !Self::is_object(input) {
+            return Err("Not an object file (bad magic)".to_string());
+        }
+        if input.len() < HEADER_SIZE {
+            return Err("Object file header is truncated".to_string());
+        }
+        if input[4] != ISA_VERSION {
+            return Err(format!(
+                "Unsupported ISA version {} (expected {})",
+                input[4], ISA_VERSION
+            ));
+        }
+
+        let mut object = Object {
+            entry: (input[5] & FLAG_ENTRY != 0).then_some(input[6]),
+            ..Default::default()
+        };
+
+        for (kind, _, content) in read_sections(&input[HEADER_SIZE - 1..])? {
+            match kind {
+                SECTION_CODE => object.code = content.to_vec(),
+                SECTION_DATA => object.data = content.to_vec(),
+                SECTION_EXPORTS => object.exports = read_exports(content)?,
+                SECTION_IMPORTS => object.imports = read_imports(content)?,
+                SECTION_RELOCATIONS => object.relocations = read_relocations(content)?,
+                _ => {},
+            }
+        }
+
+        if let Some(relocation) = object.relocations.iter().find(|relocation| {
+            let size = match relocation.section {
+                Section::Code => object.code.len(),
+                Section::Data => object.data.len(),
+            };
+            let import = match relocation.target {
+                Target::Import(import) => import as usize >= object.imports.len(),
+                _ => false,
+            };
+            relocation.offset as usize >= size || import
+        }) {
+            return Err(format!("Relocation at offset {} is out of range", relocation.offset));
+        }
+
+        Ok(object)
+    }
+}
+
+fn base_to_u8(base: Base) -> u8 {
+    match base {
+        Base::Absolute => 0,
+        Base::Code => 1,
+        Base::Data => 2,
+    }
+}
+
+/// Reads a name length and name from the start of `table` and returns the
+/// name and the rest of the table.
+fn read_name(table: &[u8]) -> Result<(String, &[u8]), String> {
+    let (length, rest) = table.split_first().ok_or("Name is truncated")?;
+    let name = rest.get(..*length as usize).ok_or("Name is truncated")?;
+    let name = String::from_utf8(name.to_vec()).map_err(|_| "Name is not valid UTF-8")?;
+    Ok((name, &rest[*length as usize..]))
+}
+
+fn read_exports(mut table: &[u8]) -> Result<Vec<Export>, String> {
+    let mut exports = Vec::new();
+    while let [base, low, high, rest @ ..] = table {
+        let base = match base {
+            0 => Base::Absolute,
+            1 => Base::Code,
+            2 => Base::Data,
+            base => return Err(format!("Unknown export base {}", base)),
+        };
+        let (name, rest) = read_name(rest)?;
+        exports.push(Export { name, base, value: i16::from_le_bytes([*low, *high]) });
+        table = rest;
+    }
+    if !table.is_empty() {
+        return Err("Export table is truncated".to_string());
+    }
+    Ok(exports)
+}
+
+fn read_imports(mut table: &[u8]) -> Result<Vec<String>, String> {
+    let mut imports = Vec::new();
+    while !table.is_empty() {
+        let (name, rest) = read_name(table)?;
+        imports.push(name);
+        table = rest;
+    }
+    Ok(imports)
+}
+
+fn read_relocations(table: &[u8]) -> Result<Vec<Relocation>, String> {
+    if !table.len().is_multiple_of(RELOCATION_SIZE) {
+        return Err("Relocation table is truncated".to_string());
+    }
+
+    table
+        .chunks(RELOCATION_SIZE)
+        .map(|entry| {
+            let section = match entry[0] {
+                SECTION_CODE => Section::Code,
+                SECTION_DATA => Section::Data,
+                section => return Err(format!("Unknown relocation section {}", section)),
+            };
+            let import = u16::from_le_bytes([entry[4], entry[5]]);
+            let target = match entry[3] {
+                1 => Target::Code,
+                2 => Target::Data,
+                3 => Target::Import(import),
+                target => return Err(format!("Unknown relocation target {}", target)),
+            };
+            Ok(Relocation {
+                section,
+                offset: u16::from_le_bytes([entry[1], entry[2]]),
+                target,
+                addend: i16::from_le_bytes([entry[6], entry[7]]),
+            })
+        })
+        .collect()
+}
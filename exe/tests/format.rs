@@ -0,0 +1,81 @@
+/*
+Tests for writing executables and reading them back, and for the errors on
+input that is not a whole executable.
+*/
+
+use exe::{DebugInfo, Executable, SourceLine, Symbol, ISA_VERSION};
+
+fn executable() -> Executable {
+    Executable {
+        entry: 2,
+        code: vec![0x60, 0x01, 0x81, 0x10, 0x00, 0x02],
+        data: vec![0xAA, 0x00, 0x55],
+        symbols: vec![
+            Symbol { name: "start".to_string(), value: 2 },
+            Symbol { name: "MINUS_ONE".to_string(), value: 0xFF },
+        ],
+        debug: Some(DebugInfo {
+            source: Some("main.asm".to_string()),
+            lines: vec![
+                SourceLine { file: None, line: Some(1) },
+                SourceLine { file: Some("lib.inc".to_string()), line: Some(7) },
+                SourceLine { file: None, line: None },
+            ],
+            labels: vec![("start".to_string(), 2)],
+        }),
+    }
+}
+
+#[test]
+fn reads_back_what_it_writes() {
+    let executable = executable();
+    let bytes = executable.to_bytes().unwrap();
+    assert!(Executable::is_executable(&bytes));
+    assert_eq!(Executable::from_bytes(&bytes).unwrap(), executable);
+
+    let code_only = Executable::new(vec![0x60, 0x01]);
+    assert_eq!(Executable::from_bytes(&code_only.to_bytes().unwrap()).unwrap(), code_only);
+}
+
+#[test]
+fn rejects_bad_magic() {
+    let mut bytes = executable().to_bytes().unwrap();
+    bytes[0] = b'X';
+    assert_eq!(Executable::from_bytes(&bytes).unwrap_err(), "Not an executable (bad magic)");
+    assert_eq!(Executable::from_bytes(b"").unwrap_err(), "Not an executable (bad magic)");
+    assert_eq!(Executable::from_bytes(&[0x60, 0x01]).unwrap_err(), "Not an executable (bad magic)");
+}
+
+#[test]
+fn rejects_other_isa_versions() {
+    let mut bytes = executable().to_bytes().unwrap();
+    bytes[4] = ISA_VERSION + 1;
+    assert_eq!(
+        Executable::from_bytes(&bytes).unwrap_err(),
+        format!("Unsupported ISA version {} (expected {})", ISA_VERSION + 1, ISA_VERSION)
+    );
+}
+
+#[test]
+fn rejects_truncated_input() {
+    let bytes = executable().to_bytes().unwrap();
+    assert_eq!(Executable::from_bytes(&bytes[..5]).unwrap_err(), "Executable header is truncated");
+    assert_eq!(Executable::from_bytes(&bytes[..9]).unwrap_err(), "Section 0 header is truncated");
+    assert_eq!(Executable::from_bytes(&bytes[..13]).unwrap_err(), "Section 0 is truncated");
+    assert_eq!(Executable::from_bytes(&bytes[..bytes.len() - 1]).unwrap_err(), "Section 3 is truncated");
+    for length in 0..bytes.len() {
+        assert!(Executable::from_bytes(&bytes[..length]).is_err(), "{} bytes", length);
+    }
+}
+
+#[test]
+fn rejects_truncated_debug_info() {
+    let mut executable = Executable::new(vec![0x60, 0x01]);
+    executable.debug = Some(DebugInfo { lines: vec![SourceLine { file: None, line: Some(1) }], ..Default::default() });
+    let mut bytes = executable.to_bytes().unwrap();
+    // drop the label count at the end and shorten the section to match
+    bytes.truncate(bytes.len() - 2);
+    let length = bytes.len() - 19;
+    bytes[17..19].copy_from_slice(&(length as u16).to_le_bytes());
+    assert_eq!(Executable::from_bytes(&bytes).unwrap_err(), "Debug section is truncated");
+}
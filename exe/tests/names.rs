@@ -0,0 +1,49 @@
+/*
+Tests for names and sections that are too long for their length fields.
+*/
+
+use exe::{object::{Base, Export, Object}, DebugInfo, Executable, Symbol};
+
+#[test]
+fn rejects_long_symbol_names() {
+    let name = "a".repeat(256);
+    let executable = Executable {
+        symbols: vec![Symbol { name: name.clone(), value: 0 }],
+        ..Default::default()
+    };
+    assert_eq!(executable.to_bytes().unwrap_err(), format!("Name {} is longer than 255 bytes", name));
+
+    let object = Object {
+        exports: vec![Export { name: name.clone(), base: Base::Absolute, value: 0 }],
+        ..Default::default()
+    };
+    assert!(object.to_bytes().is_err());
+
+    let object = Object { imports: vec![name], ..Default::default() };
+    assert!(object.to_bytes().is_err());
+}
+
+#[test]
+fn rejects_long_debug_labels() {
+    let executable = Executable {
+        debug: Some(DebugInfo { labels: vec![("b".repeat(300), 0)], ..Default::default() }),
+        ..Default::default()
+    };
+    assert!(executable.to_bytes().is_err());
+}
+
+#[test]
+fn rejects_sections_over_64k() {
+    let object = Object { data: vec![0; 0x10000], ..Default::default() };
+    assert_eq!(object.to_bytes().unwrap_err(), "Size of section 2 is 65536, more than 65535");
+}
+
+#[test]
+fn keeps_names_of_255_bytes() {
+    let executable = Executable {
+        code: vec![0x40, 0x00],
+        symbols: vec![Symbol { name: "c".repeat(255), value: 1 }],
+        ..Default::default()
+    };
+    assert_eq!(Executable::from_bytes(&executable.to_bytes().unwrap()).unwrap(), executable);
+}
@@ -0,0 +1,42 @@
+/*
+Runs random bytes as a program. The emulator has to report unknown
+instructions as errors and must never panic or overflow, whatever the code,
+entry point, data or PC it ends up at.
+
+The first byte is the entry point, the second the number of data bytes that
+follow, and the rest is the code.
+*/
+
+#![no_main]
+
+use emulator::{Machine, ROM_SIZE};
+use exe::Executable;
+use libfuzzer_sys::fuzz_target;
+
+/// Instructions to run per input, programs can loop forever.
+const MAX_CYCLES: usize = 10_000;
+
+fuzz_target!(|input: &[u8]| {
+    let [entry, data_size, rest @ ..] = input else { return };
+    let (data, code) = rest.split_at((*data_size as usize).min(rest.len()));
+    let program = Executable {
+        entry: *entry,
+        code: code[..code.len().min(ROM_SIZE)].to_vec(),
+        data: data.to_vec(),
+        symbols: Vec::new(),
+        debug: None,
+    };
+
+    let Ok(mut machine) = Machine::new(&program) else { return };
+    for _ in 0..MAX_CYCLES {
+        match machine.step() {
+            Ok(emulator::Step::Halted) | Err(_) => break,
+            Ok(_) => {},
+        }
+    }
+
+    // the disassembler decodes the same bytes and must not panic either
+    for pair in program.code.chunks_exact(2) {
+        let _ = emulator::disassembler::instruction([pair[0], pair[1]]);
+    }
+});
@@ -0,0 +1,170 @@
+/*
+Generates valid assembly from random bytes, assembles it, disassembles the
+result and assembles that again. Both assemblies have to give the same bytes,
+and the generated source has to assemble in the first place.
+
+The generator uses labels, pseudo-instructions, every way of writing a
+register and several ways of writing a constant, and keeps to the rules the
+assembler checks: at most 256 instructions, no multi-instruction
+pseudo-instruction after a skip and no `inc`/`dec` of VF.
+*/
+
+#![no_main]
+
+use assembler::Options;
+use emulator::disassembler;
+use libfuzzer_sys::fuzz_target;
+
+/// Labels the generated code can place and jump to.
+const LABELS: u8 = 4;
+/// Instructions to stop generating at, leaving room for the last expansion.
+const MAX_INSTRUCTIONS: usize = 250;
+
+struct Generator<'a> {
+    input: &'a [u8],
+    source: String,
+    instructions: usize,
+    after_skip: bool,
+    placed: [bool; LABELS as usize],
+}
+
+impl Generator<'_> {
+    fn byte(&mut self) -> Option<u8> {
+        let (first, rest) = self.input.split_first()?;
+        self.input = rest;
+        Some(*first)
+    }
+
+    fn register(&mut self) -> Option<String> {
+        let byte = self.byte()?;
+        let register = byte & 0x0F;
+        Some(match byte >> 4 & 0x3 {
+            0 => format!("V{:X}", register),
+            1 => format!("v{:x}", register),
+            _ => register.to_string(),
+        })
+    }
+
+    fn constant(&mut self) -> Option<String> {
+        let value = self.byte()?;
+        Some(match self.byte()? % 5 {
+            0 => value.to_string(),
+            1 => format!("0x{:02X}", value),
+            2 => format!("0b{:b}", value),
+            3 if value.is_ascii_alphanumeric() => format!("'{}'", value as char),
+            _ => format!("{} + {}", value / 2, value - value / 2),
+        })
+    }
+
+    fn target(&mut self) -> Option<String> {
+        let byte = self.byte()?;
+        Some(match byte % (LABELS + 1) {
+            LABELS => format!("0x{:02X}", byte),
+            label => format!("@L{}", label),
+        })
+    }
+
+    fn two_registers(&mut self, mnemonic: &str) -> Option<String> {
+        Some(format!("{} {} {}", mnemonic, self.register()?, self.register()?))
+    }
+
+    fn branch(&mut self, mnemonic: &str) -> Option<String> {
+        Some(format!("{} {} {} {}", mnemonic, self.register()?, self.register()?, self.target()?))
+    }
+
+    /// Adds one line, or returns `None` when the input runs out.
+    fn line(&mut self) -> Option<()> {
+        // (line, instructions it expands to, is a skip)
+        let (line, size, skip) = match self.byte()? % 29 {
+            0 => (format!("jump {}", self.target()?), 1, false),
+            1 => (self.two_registers("ifeq")?, 1, true),
+            2 => (self.two_registers("ifneq")?, 1, true),
+            3 => (self.two_registers("ifle")?, 1, true),
+            4 => (self.two_registers("setrr")?, 1, false),
+            5 => (format!("setrpc {}", self.register()?), 1, false),
+            6 => (format!("setrm {} {}", self.register()?, self.constant()?), 1, false),
+            7 => (format!("setrc {} {}", self.register()?, self.constant()?), 1, false),
+            8 => (format!("setpcr {}", self.register()?), 1, false),
+            9 => (format!("setmr {} {}", self.register()?, self.constant()?), 1, false),
+            10 => (self.two_registers("add")?, 1, false),
+            11 => (self.two_registers("sub")?, 1, false),
+            12 => (self.two_registers("and")?, 1, false),
+            13 => (self.two_registers("or")?, 1, false),
+            14 => (self.two_registers("xor")?, 1, false),
+            15 => (format!("not {}", self.register()?), 1, false),
+            16 => ("nop".to_string(), 1, false),
+            17 => (self.two_registers("mov")?, 1, false),
+            18 => (format!("mov {} {}", self.register()?, self.constant()?), 1, false),
+            19 => (format!("clr {}", self.register()?), 1, false),
+            20 => (format!("print {}", self.register()?), 1, false),
+            21 => (self.two_registers("ifge")?, 1, true),
+            22 | 23 => {
+                let label = self.byte()? % LABELS;
+                if !self.placed[label as usize] {
+                    self.placed[label as usize] = true;
+                    self.source.push_str(&format!("@L{}\n", label));
+                }
+                return Some(());
+            },
+            24 | 25 => {
+                let register = self.byte()? % 0xF;
+                let mnemonic = if register % 2 == 0 { "inc" } else { "dec" };
+                (format!("{} V{:X}", mnemonic, register), 2, false)
+            },
+            26 => (self.two_registers("ifgt")?, 2, true),
+            27 => {
+                let mnemonic = ["beq", "bne", "blt", "bgt"][self.byte()? as usize % 4];
+                (self.branch(mnemonic)?, 2, false)
+            },
+            _ => {
+                let mnemonic = if self.byte()? % 2 == 0 { "ble" } else { "bge" };
+                (self.branch(mnemonic)?, 3, false)
+            },
+        };
+
+        // a skip only skips the first instruction of an expansion
+        let (line, size, skip) = if self.after_skip && size > 1 { ("nop".to_string(), 1, false) } else { (line, size, skip) };
+        self.source.push_str(&line);
+        self.source.push('\n');
+        self.instructions += size;
+        self.after_skip = skip;
+        Some(())
+    }
+
+    fn generate(mut self) -> String {
+        while self.instructions < MAX_INSTRUCTIONS && self.line().is_some() {}
+        // labels that are jumped to but never placed end up after the last instruction
+        for (label, placed) in self.placed.iter().enumerate() {
+            if !placed {
+                self.source.push_str(&format!("@L{}\n", label));
+            }
+        }
+        self.source
+    }
+}
+
+fuzz_target!(|input: &[u8]| {
+    let generator = Generator {
+        input,
+        source: String::new(),
+        instructions: 0,
+        after_skip: false,
+        placed: [false; LABELS as usize],
+    };
+    let source = generator.generate();
+
+    let code = assemble(&source)
+        .unwrap_or_else(|e| panic!("generated source does not assemble: {}\n{}", e, source));
+    let disassembly = disassembler::disassemble(&code)
+        .unwrap_or_else(|e| panic!("assembled code does not disassemble: {}\n{}", e, source));
+    let reassembled = assemble(&disassembly)
+        .unwrap_or_else(|e| panic!("disassembly does not assemble: {}\n{}", e, disassembly));
+
+    assert_eq!(code, reassembled, "reassembled code differs\n{}\n{}", source, disassembly);
+});
+
+fn assemble(source: &str) -> Result<Vec<u8>, String> {
+    assembler::assemble(source, &Options::default())
+        .map(|assembled| assembled.program.code)
+        .map_err(|diagnostics| diagnostics.iter().map(ToString::to_string).collect::<Vec<_>>().join("\n"))
+}
@@ -0,0 +1,151 @@
+/*
+The linker combines object files from `assembler -c` into one program. The
+code and data of the modules are placed one after another in the order they
+are given, `.global` symbols are collected into one table and every
+relocation is patched with the final address of its target.
+
+Errors for all modules are collected and reported together, one per line.
+*/
+
+use std::collections::HashMap;
+
+use exe::{object::{Base, Object, Section, Target}, Executable, Symbol, MEMORY_SIZE};
+
+/// Number of instructions the 8-bit PC can address.
+const MAX_INSTRUCTIONS: usize = 256;
+
+/// An object file and the name it is reported by.
+pub struct Module {
+    pub name: String,
+    pub object: Object,
+}
+
+/// Where a module ends up in the program.
+struct Placement {
+    /// PC of the first instruction of the module.
+    code: usize,
+    /// Address of the first byte of data of the module.
+    data: usize,
+}
+
+/// Links the modules into an executable with the global symbols as its
+/// symbol table.
+pub fn link(modules: &[Module]) -> Result<Executable, String> {
+    let mut errors = Vec::new();
+
+    let mut placements = Vec::new();
+    let (mut code_size, mut data_size) = (0, 0);
+    for module in modules {
+        placements.push(Placement { code: code_size / 2, data: data_size });
+        code_size += module.object.code.len();
+        data_size += module.object.data.len();
+    }
+    if code_size / 2 > MAX_INSTRUCTIONS {
+        return Err(format!(
+            "The program has {} instructions, more than the {} the PC can address",
+            code_size / 2, MAX_INSTRUCTIONS
+        ));
+    }
+    if data_size > MEMORY_SIZE {
+        return Err(format!("The program has {} bytes of data, more than the {} bytes of memory", data_size, MEMORY_SIZE));
+    }
+
+    // global name -> (value, defining module)
+    let mut globals: HashMap<&str, (i64, &str)> = HashMap::new();
+    for (module, placement) in modules.iter().zip(&placements) {
+        for export in &module.object.exports {
+            let value = export.value as i64 + match export.base {
+                Base::Absolute => 0,
+                Base::Code => placement.code as i64,
+                Base::Data => placement.data as i64,
+            };
+            if let Some((_, previous)) = globals.insert(&export.name, (value, &module.name)) {
+                errors.push(format!(
+                    "{}: duplicate symbol @{}, already defined in {}",
+                    module.name, export.name, previous
+                ));
+            }
+        }
+    }
+
+    let mut executable = Executable::default();
+    let mut entry = None;
+
+    for (module, placement) in modules.iter().zip(&placements) {
+        let object = &module.object;
+        let code_start = executable.code.len();
+        let data_start = executable.data.len();
+        executable.code.extend_from_slice(&object.code);
+        executable.data.extend_from_slice(&object.data);
+
+        let mut undefined = Vec::new();
+        for relocation in &object.relocations {
+            let base = match relocation.target {
+                Target::Code => placement.code as i64,
+                Target::Data => placement.data as i64,
+                Target::Import(import) => {
+                    let name = &object.imports[import as usize];
+                    match globals.get(name.as_str()) {
+                        Some((value, _)) => *value,
+                        None => {
+                            if !undefined.contains(&name) {
+                                errors.push(format!("{}: undefined symbol @{}", module.name, name));
+                                undefined.push(name);
+                            }
+                            continue;
+                        },
+                    }
+                },
+            };
+
+            let value = base + relocation.addend as i64;
+            if !(-0x80..=0xFF).contains(&value) {
+                errors.push(format!(
+                    "{}: {} does not fit in 8 bits at {} offset {}",
+                    module.name, value, describe(relocation.section), relocation.offset
+                ));
+                continue;
+            }
+            let offset = relocation.offset as usize;
+            match relocation.section {
+                Section::Code => executable.code[code_start + offset] = value as u8,
+                Section::Data => executable.data[data_start + offset] = value as u8,
+            }
+        }
+
+        if let Some(module_entry) = object.entry {
+            match entry {
+                Some((_, previous)) => errors.push(format!(
+                    "{}: entry point is already set in {}",
+                    module.name, previous
+                )),
+                None => entry = Some((placement.code + module_entry as usize, &module.name)),
+            }
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(errors.join("\n"));
+    }
+
+    executable.entry = entry.map_or(0, |(entry, _)| entry as u8);
+
+    // like the assembler, negative constants are stored as two's complement
+    // and constants that do not fit in 8 bits are left out
+    let mut symbols = globals
+        .iter()
+        .filter(|(_, (value, _))| (-0x80..=0xFF).contains(value))
+        .map(|(name, (value, _))| Symbol { name: name.to_string(), value: *value as u8 })
+        .collect::<Vec<_>>();
+    symbols.sort_by(|a, b| a.name.cmp(&b.name));
+    executable.symbols = symbols;
+
+    Ok(executable)
+}
+
+fn describe(section: Section) -> &'static str {
+    match section {
+        Section::Code => "code",
+        Section::Data => "data",
+    }
+}
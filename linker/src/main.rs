@@ -0,0 +1,115 @@
+use std::{env::args, fs, io::{self, Write}, path::Path, process::ExitCode};
+
+use assembler::output::{self, Format};
+use exe::object::Object;
+use linker::Module;
+
+const USAGE: &str = "\
+Usage: linker [options] <file.o>...
+
+Options:
+    -o <file>          Write output to <file> (default: the first input with the extension of the format)
+    --format <format>  Output format: bin, hex, ihex, srec or exe (default: bin)
+    --stdout           Write output to stdout instead of a file
+    -h, --help         Print this help";
+
+struct Options {
+    output: Option<String>,
+    format: Format,
+    stdout: bool,
+    inputs: Vec<String>,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Option<Options>, String> {
+    let mut options = Options {
+        output: None,
+        format: Format::Bin,
+        stdout: false,
+        inputs: Vec::new(),
+    };
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" => {
+                options.output = Some(args.next().ok_or("-o expects a file")?);
+            },
+            "--format" => {
+                options.format = args.next().ok_or("--format expects a format")?.parse()?;
+            },
+            "--stdout" => {
+                options.stdout = true;
+            },
+            "-h" | "--help" => {
+                return Ok(None);
+            },
+            _ if arg.starts_with('-') => {
+                return Err(format!("Unknown option: {}", arg));
+            },
+            _ => {
+                options.inputs.push(arg);
+            }
+        }
+    }
+
+    if options.inputs.is_empty() {
+        return Err("No object files provided".to_string());
+    }
+    if options.output.is_some() && options.stdout {
+        return Err("-o and --stdout can not be used together".to_string());
+    }
+
+    Ok(Some(options))
+}
+
+fn main() -> ExitCode {
+    let options = match parse_args(args().skip(1)) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        },
+        Err(e) => {
+            eprintln!("error: {}", e);
+            eprintln!("{}", USAGE);
+            return ExitCode::from(2);
+        }
+    };
+
+    match link(&options) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            e.lines().for_each(|line| eprintln!("error: {}", line));
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn link(options: &Options) -> Result<(), String> {
+    let modules = options.inputs
+        .iter()
+        .map(|input| {
+            let bytes = fs::read(input).map_err(|e| format!("{}: {}", input, e))?;
+            let object = Object::from_bytes(&bytes).map_err(|e| format!("{}: {}", input, e))?;
+            Ok(Module { name: input.clone(), object })
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    let executable = linker::link(&modules)?;
+    if !executable.data.is_empty() && options.format != Format::Exe {
+        return Err(format!("the program has a .data section, which only the exe format can hold (not {})", options.format));
+    }
+    let encoded = output::encode(&executable, options.format)?;
+
+    if options.stdout {
+        return io::stdout()
+            .write_all(&encoded)
+            .map_err(|e| e.to_string());
+    }
+
+    let path = match &options.output {
+        Some(path) => path.into(),
+        None => Path::new(&options.inputs[0]).with_extension(options.format.extension()),
+    };
+
+    fs::write(&path, encoded).map_err(|e| format!("{}: {}", path.display(), e))
+}
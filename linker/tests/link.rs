@@ -0,0 +1,93 @@
+/*
+Tests for linking hand-built object files: symbol resolution, relocation
+patching and the errors for broken programs.
+*/
+
+use exe::object::{Base, Export, Object, Relocation, Section, Target};
+use linker::{link, Module};
+
+fn module(name: &str, object: Object) -> Module {
+    Module { name: name.to_string(), object }
+}
+
+fn export(name: &str, base: Base, value: i16) -> Export {
+    Export { name: name.to_string(), base, value }
+}
+
+fn relocation(section: Section, offset: u16, target: Target, addend: i16) -> Relocation {
+    Relocation { section, offset, target, addend }
+}
+
+/// `jump @f` to an import, with the entry point at its start.
+fn main_module() -> Object {
+    Object {
+        entry: Some(0),
+        code: vec![0x00, 0x00],
+        imports: vec!["f".to_string()],
+        relocations: vec![relocation(Section::Code, 1, Target::Import(0), 0)],
+        ..Default::default()
+    }
+}
+
+/// Defines `@f` at its second instruction, which loads the address of its
+/// data.
+fn library() -> Object {
+    Object {
+        code: vec![0x40, 0x00, 0x61, 0x00],
+        data: vec![7],
+        exports: vec![export("f", Base::Code, 1)],
+        relocations: vec![relocation(Section::Code, 3, Target::Data, 0)],
+        ..Default::default()
+    }
+}
+
+#[test]
+fn patches_relocations() {
+    let data = Object {
+        data: vec![1, 2, 0],
+        relocations: vec![relocation(Section::Data, 2, Target::Data, 1)],
+        ..Default::default()
+    };
+    let modules = [module("main.o", main_module()), module("data.o", data), module("lib.o", library())];
+    let executable = link(&modules).unwrap();
+
+    // main.o is at PC 0, lib.o at PC 1 after it, so @f is at PC 2
+    assert_eq!(executable.code, [0x00, 0x02, 0x40, 0x00, 0x61, 0x03]);
+    // data.o points at its second byte, lib.o's data is after data.o's
+    assert_eq!(executable.data, [1, 2, 1, 7]);
+    assert_eq!(executable.entry, 0);
+    assert_eq!(executable.symbols.len(), 1);
+    assert_eq!((executable.symbols[0].name.as_str(), executable.symbols[0].value), ("f", 2));
+}
+
+#[test]
+fn links_modules_read_back_from_bytes() {
+    let objects = [main_module(), library()].map(|object| Object::from_bytes(&object.to_bytes().unwrap()).unwrap());
+    let [main, lib] = objects;
+    let executable = link(&[module("main.o", main), module("lib.o", lib)]).unwrap();
+    assert_eq!(executable.code, [0x00, 0x02, 0x40, 0x00, 0x61, 0x00]);
+}
+
+#[test]
+fn reports_undefined_symbols() {
+    let mut other = main_module();
+    other.entry = None;
+    let error = link(&[module("main.o", main_module()), module("other.o", other)]).unwrap_err();
+    assert_eq!(error, "main.o: undefined symbol @f\nother.o: undefined symbol @f");
+}
+
+#[test]
+fn reports_duplicate_globals() {
+    let modules = [module("main.o", main_module()), module("a.o", library()), module("b.o", library())];
+    assert_eq!(link(&modules).unwrap_err(), "b.o: duplicate symbol @f, already defined in a.o");
+}
+
+#[test]
+fn reports_values_that_do_not_fit() {
+    let object = Object {
+        code: vec![0x60, 0x00],
+        relocations: vec![relocation(Section::Code, 1, Target::Code, 0x100)],
+        ..Default::default()
+    };
+    assert_eq!(link(&[module("a.o", object)]).unwrap_err(), "a.o: 256 does not fit in 8 bits at code offset 1");
+}